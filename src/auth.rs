@@ -0,0 +1,59 @@
+use reqwest::RequestBuilder;
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::{Error, Result};
+
+/// The error a [`TokenProvider`] can fail with
+pub type TokenError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Supplies bearer tokens for requests to the registry, for when tokens expire and need to be
+/// refreshed, such as with an OAuth client credentials flow
+///
+/// The provider is asked for a token before every request, so it should hold on to its current
+/// token and only fetch a new one when that is about to expire.
+pub trait TokenProvider: Send + Sync {
+    fn token(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<String, TokenError>> + Send + '_>>;
+}
+
+/// How requests to the registry are authenticated
+#[derive(Clone)]
+pub(crate) enum Authentication {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+    Provider(Arc<dyn TokenProvider>),
+}
+
+impl Authentication {
+    pub(crate) async fn apply(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        let request = match self {
+            Self::Basic { username, password } => request.basic_auth(username, password.as_ref()),
+            Self::Bearer(token) => request.bearer_auth(token),
+            Self::Provider(provider) => {
+                let token = provider.token().await.map_err(Error::TokenProvider)?;
+                request.bearer_auth(token)
+            }
+        };
+        Ok(request)
+    }
+}
+
+/// Credentials are left out, so they can't end up in logs
+impl fmt::Debug for Authentication {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Basic { .. } => "Basic",
+            Self::Bearer(_) => "Bearer",
+            Self::Provider(_) => "Provider",
+        };
+        f.debug_tuple("Authentication").field(&kind).finish()
+    }
+}
@@ -0,0 +1,299 @@
+use reqwest::Client;
+#[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+use reqwest::{Certificate, Identity};
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::auth::{Authentication, TokenProvider};
+use crate::nodes::FailoverStrategy;
+use crate::retry::RetryPolicy;
+use crate::{Result, SchemaRegistry};
+
+const DEFAULT_PREFETCH_CONCURRENCY: usize = 8;
+const DEFAULT_UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Configures a [`SchemaRegistry`] before it is created
+///
+/// ```no_run
+/// # use confluent_schema_registry::SchemaRegistry;
+/// # use std::time::Duration;
+/// let registry = SchemaRegistry::builder("http://localhost:8081")
+///     .latest_ttl(Duration::from_secs(300))
+///     .build()?;
+/// # Ok::<(), confluent_schema_registry::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SchemaRegistryBuilder {
+    pub(crate) urls: Vec<String>,
+    pub(crate) failover: FailoverStrategy,
+    pub(crate) unhealthy_cooldown: Duration,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) client: Option<Client>,
+    pub(crate) auth: Option<Authentication>,
+    pub(crate) latest_ttl: Option<Duration>,
+    pub(crate) prefetch_concurrency: usize,
+    pub(crate) max_cached_schemas: Option<usize>,
+    pub(crate) max_cache_size: Option<usize>,
+    pub(crate) disk_cache: Option<PathBuf>,
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    root_certificates: Vec<Pem>,
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    identity: Option<(Pem, Pem)>,
+}
+
+/// PEM encoded data, or a file to read it from when the registry is built
+#[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+#[derive(Debug, Clone)]
+enum Pem {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+impl Pem {
+    fn read(self) -> Result<Vec<u8>> {
+        match self {
+            Self::Bytes(bytes) => Ok(bytes),
+            Self::File(path) => Ok(std::fs::read(path)?),
+        }
+    }
+}
+
+impl SchemaRegistryBuilder {
+    /// `url` can be a comma separated list of URLs, the same as other Confluent clients accept
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            urls: Vec::new(),
+            failover: FailoverStrategy::default(),
+            unhealthy_cooldown: DEFAULT_UNHEALTHY_COOLDOWN,
+            retry_policy: RetryPolicy::none(),
+            client: None,
+            auth: None,
+            latest_ttl: None,
+            prefetch_concurrency: DEFAULT_PREFETCH_CONCURRENCY,
+            max_cached_schemas: None,
+            max_cache_size: None,
+            disk_cache: None,
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+            root_certificates: Vec::new(),
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+            identity: None,
+        }
+        .add_url(url)
+    }
+
+    /// Adds another URL (or comma separated list of URLs) for the registry, which is failed over
+    /// to when the others can't be reached or respond with a server error
+    pub fn add_url(mut self, url: impl Into<String>) -> Self {
+        let url = url.into();
+        self.urls.extend(
+            url.split(',')
+                .map(|url| url.trim().trim_end_matches('/'))
+                .filter(|url| !url.is_empty())
+                .map(str::to_owned),
+        );
+        self
+    }
+
+    /// The order the registry URLs are tried in, by default they are tried in the order they were
+    /// added
+    pub fn failover(mut self, strategy: FailoverStrategy) -> Self {
+        self.failover = strategy;
+        self
+    }
+
+    /// How long a URL that has failed is tried last for, defaults to 30 seconds
+    pub fn unhealthy_cooldown(mut self, cooldown: Duration) -> Self {
+        self.unhealthy_cooldown = cooldown;
+        self
+    }
+
+    /// Sends requests with the given client rather than a default one
+    ///
+    /// The client is used as is, so this can't be combined with any of the TLS options
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Retries requests that fail with a transient error, by default requests are never retried
+    ///
+    /// Retries happen after failing over, so each attempt tries every registry URL. The delays
+    /// between attempts are timed with Tokio, so retrying needs a Tokio runtime with its timer
+    /// enabled.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Authenticates with HTTP basic auth, which is what Confluent Cloud expects its API key and
+    /// secret to be sent as
+    pub fn basic_auth(mut self, username: impl Into<String>, password: Option<String>) -> Self {
+        self.auth = Some(Authentication::Basic {
+            username: username.into(),
+            password,
+        });
+        self
+    }
+
+    /// Authenticates with a bearer token that never changes
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(Authentication::Bearer(token.into()));
+        self
+    }
+
+    /// Authenticates with bearer tokens from the provider, which is asked for a token before every
+    /// request
+    pub fn token_provider(mut self, provider: impl TokenProvider + 'static) -> Self {
+        self.auth = Some(Authentication::Provider(Arc::new(provider)));
+        self
+    }
+
+    /// How long the id of the latest version of a subject is trusted for before it is looked up
+    /// again
+    ///
+    /// The lookup happens lazily, the next time the latest version of the subject is asked for.
+    /// By default the latest version is cached until the subject is invalidated.
+    pub fn latest_ttl(mut self, ttl: Duration) -> Self {
+        self.latest_ttl = Some(ttl);
+        self
+    }
+
+    /// How many schemas [`prefetch`](SchemaRegistry::prefetch) and
+    /// [`prefetch_ids`](SchemaRegistry::prefetch_ids) fetch from the registry at once, defaults to
+    /// 8
+    pub fn prefetch_concurrency(mut self, max: usize) -> Self {
+        self.prefetch_concurrency = max.max(1);
+        self
+    }
+
+    /// Limits how many parsed schemas are cached, evicting the least recently used ones first
+    ///
+    /// By default every schema the registry has parsed is kept
+    pub fn max_cached_schemas(mut self, max: usize) -> Self {
+        self.max_cached_schemas = Some(max);
+        self
+    }
+
+    /// Limits the approximate size of the cached schemas, evicting the least recently used ones
+    /// first
+    ///
+    /// The size of a schema is taken as the length of its raw schema, which is a rough guide to
+    /// how much memory the parsed schema takes up
+    pub fn max_cache_size(mut self, bytes: usize) -> Self {
+        self.max_cache_size = Some(bytes);
+        self
+    }
+
+    /// Keeps a copy of every schema fetched from the registry in the directory, as `{id}.json`
+    /// files, and loads them back when the registry is built
+    ///
+    /// This lets a service deserialize any schema it has seen before while the registry is
+    /// unreachable. Only schemas are stored, so looking up a subject still needs the registry.
+    pub fn disk_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.disk_cache = Some(dir.into());
+        self
+    }
+
+    /// Trusts the PEM encoded CA certificate, on top of the system's root certificates
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    pub fn root_certificate(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.root_certificates.push(Pem::Bytes(pem.into()));
+        self
+    }
+
+    /// Trusts the PEM encoded CA certificate in the file, which is read when the registry is built
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    pub fn root_certificate_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.root_certificates.push(Pem::File(path.into()));
+        self
+    }
+
+    /// Presents the PEM encoded certificate (chain) to the registry for mutual TLS, along with its
+    /// PEM encoded private key
+    ///
+    /// With the `native-tls` feature the key has to be in PKCS #8 format
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    pub fn client_identity(
+        mut self,
+        certificate_pem: impl Into<Vec<u8>>,
+        key_pem: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.identity = Some((
+            Pem::Bytes(certificate_pem.into()),
+            Pem::Bytes(key_pem.into()),
+        ));
+        self
+    }
+
+    /// Same as [`client_identity`](Self::client_identity), with the certificate and key read from
+    /// files when the registry is built
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    pub fn client_identity_files(
+        mut self,
+        certificate_path: impl Into<PathBuf>,
+        key_path: impl Into<PathBuf>,
+    ) -> Self {
+        self.identity = Some((
+            Pem::File(certificate_path.into()),
+            Pem::File(key_path.into()),
+        ));
+        self
+    }
+
+    /// Fails if no registry URLs were given, or if any of the TLS certificates or keys can't be
+    /// read or parsed
+    pub fn build(mut self) -> Result<SchemaRegistry> {
+        if self.urls.is_empty() {
+            return Err(crate::Error::InvalidConfig(
+                "At least one registry URL is needed".to_owned(),
+            ));
+        }
+        let client = self.build_client()?;
+        Ok(SchemaRegistry::from_builder(self, client))
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    fn build_client(&mut self) -> Result<Client> {
+        let has_tls_options = !self.root_certificates.is_empty() || self.identity.is_some();
+        if let Some(client) = self.client.take() {
+            if has_tls_options {
+                return Err(crate::Error::InvalidConfig(
+                    "TLS options can't be applied to a client that has already been built"
+                        .to_owned(),
+                ));
+            }
+            return Ok(client);
+        }
+        let mut builder = Client::builder();
+        for pem in self.root_certificates.drain(..) {
+            builder = builder.add_root_certificate(Certificate::from_pem(&pem.read()?)?);
+        }
+        if let Some((certificate, key)) = self.identity.take() {
+            builder = builder.identity(identity(certificate.read()?, key.read()?)?);
+        }
+        Ok(builder.build()?)
+    }
+
+    #[cfg(not(any(feature = "native-tls", feature = "rustls-tls")))]
+    fn build_client(&mut self) -> Result<Client> {
+        match self.client.take() {
+            Some(client) => Ok(client),
+            None => Ok(Client::builder().build()?),
+        }
+    }
+}
+
+#[cfg(feature = "native-tls")]
+fn identity(certificate: Vec<u8>, key: Vec<u8>) -> Result<Identity> {
+    Ok(Identity::from_pkcs8_pem(&certificate, &key)?)
+}
+
+#[cfg(all(feature = "rustls-tls", not(feature = "native-tls")))]
+fn identity(mut certificate: Vec<u8>, key: Vec<u8>) -> Result<Identity> {
+    certificate.push(b'\n');
+    certificate.extend(key);
+    Ok(Identity::from_pem(&certificate)?)
+}
@@ -0,0 +1,228 @@
+use dashmap::DashMap;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::schema::{Format, ResolvedReference, Schema};
+
+/// Counters describing how the registry's schema cache has been used
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups of a schema id that were answered from the cache
+    pub hits: u64,
+    /// Lookups of a schema id that weren't in the cache
+    pub misses: u64,
+    /// Schemas dropped from the cache to stay within its limits
+    pub evictions: u64,
+    /// Schemas currently cached
+    pub entries: usize,
+    /// Approximate size of the schemas currently cached, in bytes of raw schema text (including
+    /// any schemas they reference)
+    pub size: usize,
+}
+
+/// A copy of everything a registry has cached, see
+/// [`SchemaRegistry::export_cache`](crate::SchemaRegistry::export_cache)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheSnapshot {
+    pub(crate) schemas: BTreeMap<u32, RawSchema>,
+    pub(crate) latest: BTreeMap<String, u32>,
+    pub(crate) versions: Vec<CachedVersion>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedVersion {
+    pub(crate) subject: String,
+    pub(crate) version: u32,
+    pub(crate) id: u32,
+}
+
+/// Everything needed to parse a schema again without going to the registry, including the raw
+/// schemas it references
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RawSchema {
+    #[serde(rename = "schemaType")]
+    pub(crate) format: Format,
+    pub(crate) schema: String,
+    #[serde(default)]
+    pub(crate) references: Vec<ResolvedReference>,
+}
+
+impl RawSchema {
+    pub(crate) fn parse(&self) -> crate::Result<Schema> {
+        self.format
+            .parse_schema_with_references(&self.schema, &self.references)
+    }
+
+    /// Used to approximate how much memory the parsed schema takes up
+    fn size(&self) -> usize {
+        self.schema.len()
+            + self
+                .references
+                .iter()
+                .map(|r| r.schema.len())
+                .sum::<usize>()
+    }
+}
+
+/// The parsed schemas held by a registry, keyed by id, along with the raw schemas they were
+/// parsed from
+///
+/// Without any limits this is a plain concurrent map. With limits, the least recently used schemas
+/// are evicted once there are too many of them, or their raw schemas add up to too many bytes.
+pub(crate) struct SchemaCache {
+    store: Store,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+enum Store {
+    Unbounded(DashMap<u32, Entry>),
+    Bounded(Mutex<Bounded>),
+}
+
+struct Entry {
+    schema: Arc<Schema>,
+    raw: Arc<RawSchema>,
+    size: usize,
+}
+
+struct Bounded {
+    entries: LruCache<u32, Entry>,
+    size: usize,
+    max_entries: Option<usize>,
+    max_size: Option<usize>,
+}
+
+impl Bounded {
+    fn is_over_limit(&self) -> bool {
+        self.max_entries.is_some_and(|max| self.entries.len() > max)
+            || self.max_size.is_some_and(|max| self.size > max)
+    }
+}
+
+impl Default for SchemaCache {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+impl SchemaCache {
+    pub(crate) fn new(max_entries: Option<usize>, max_size: Option<usize>) -> Self {
+        let store = if max_entries.is_none() && max_size.is_none() {
+            Store::Unbounded(DashMap::new())
+        } else {
+            Store::Bounded(Mutex::new(Bounded {
+                entries: LruCache::unbounded(),
+                size: 0,
+                max_entries,
+                max_size,
+            }))
+        };
+        Self {
+            store,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn get(&self, id: u32) -> Option<Arc<Schema>> {
+        let schema = match &self.store {
+            Store::Unbounded(entries) => entries.get(&id).map(|e| Arc::clone(&e.schema)),
+            Store::Bounded(bounded) => lock(bounded)
+                .entries
+                .get(&id)
+                .map(|e| Arc::clone(&e.schema)),
+        };
+        let counter = if schema.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        schema
+    }
+
+    pub(crate) fn insert(&self, id: u32, schema: Arc<Schema>, raw: Arc<RawSchema>) {
+        let size = raw.size();
+        let entry = Entry { schema, raw, size };
+        match &self.store {
+            Store::Unbounded(entries) => {
+                entries.insert(id, entry);
+            }
+            Store::Bounded(bounded) => {
+                let mut bounded = lock(bounded);
+                bounded.size += size;
+                if let Some(old) = bounded.entries.put(id, entry) {
+                    bounded.size -= old.size;
+                }
+                // Always keep the schema that was just inserted, even if it is over the limit on
+                // its own, as it is about to be used
+                while bounded.is_over_limit() && bounded.entries.len() > 1 {
+                    if let Some((_, evicted)) = bounded.entries.pop_lru() {
+                        bounded.size -= evicted.size;
+                        self.evictions.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn remove(&self, id: u32) {
+        match &self.store {
+            Store::Unbounded(entries) => {
+                entries.remove(&id);
+            }
+            Store::Bounded(bounded) => {
+                let mut bounded = lock(bounded);
+                if let Some(old) = bounded.entries.pop(&id) {
+                    bounded.size -= old.size;
+                }
+            }
+        }
+    }
+
+    /// The raw schema behind every cached id, without affecting how recently each was used
+    pub(crate) fn raw_schemas(&self) -> Vec<(u32, Arc<RawSchema>)> {
+        match &self.store {
+            Store::Unbounded(entries) => entries
+                .iter()
+                .map(|e| (*e.key(), Arc::clone(&e.value().raw)))
+                .collect(),
+            Store::Bounded(bounded) => lock(bounded)
+                .entries
+                .iter()
+                .map(|(id, e)| (*id, Arc::clone(&e.raw)))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        let (entries, size) = match &self.store {
+            Store::Unbounded(entries) => {
+                (entries.len(), entries.iter().map(|e| e.value().size).sum())
+            }
+            Store::Bounded(bounded) => {
+                let bounded = lock(bounded);
+                (bounded.entries.len(), bounded.size)
+            }
+        };
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries,
+            size,
+        }
+    }
+}
+
+/// Nothing can panic while the lock is held, but if it somehow did the cache is still usable
+fn lock(bounded: &Mutex<Bounded>) -> std::sync::MutexGuard<'_, Bounded> {
+    bounded.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+/// The compatibility rules the registry enforces when new versions of a schema are registered
+///
+/// The transitive variants check against every previously registered version of the subject,
+/// rather than only the latest one.
+///
+/// For more detailed reading, you can find confluents documentation
+/// [here](https://docs.confluent.io/platform/current/schema-registry/avro.html#compatibility-types)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CompatibilityLevel {
+    None,
+    Backward,
+    BackwardTransitive,
+    Forward,
+    ForwardTransitive,
+    Full,
+    FullTransitive,
+}
+
+/// The outcome of checking a schema's compatibility against the registry
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CompatibilityCheck {
+    pub is_compatible: bool,
+    /// The reasons the registry gave for the schema being incompatible
+    #[serde(default)]
+    pub messages: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CompatibilityRequest {
+    pub(crate) compatibility: CompatibilityLevel,
+}
+
+/// The registry returns the level under `compatibilityLevel` when it's read, and under
+/// `compatibility` when it's updated
+#[derive(Debug, Deserialize)]
+pub(crate) struct CompatibilityResponse {
+    #[serde(alias = "compatibilityLevel")]
+    pub(crate) compatibility: CompatibilityLevel,
+}
@@ -1,108 +1,234 @@
-use futures_locks::RwLock;
+use arc_swap::ArcSwap;
+#[cfg(feature = "avro")]
+use avro_rs::Schema as AvroSchema;
 use serde::de::DeserializeOwned;
 
+use std::collections::HashMap;
+#[cfg(feature = "avro")]
 use std::io::Cursor;
 use std::sync::Arc;
 
+#[cfg(feature = "json")]
+use crate::json;
+#[cfg(feature = "proto")]
+use crate::proto;
+#[cfg(feature = "avro")]
+use crate::schema::SchemaDetails;
 use crate::schema::{Format, Schema};
 use crate::schema_registry::SchemaRef;
 use crate::{Error, Result, SchemaRegistry};
 
-#[derive(Clone, Copy)]
+/// The schemas a cached deserializer has seen, keyed by id
+///
+/// Reads are a lock-free load of the current map. Inserts copy the map, which is cheap as it only
+/// ever holds the handful of schemas the deserializer has come across, and is shared between
+/// clones of the deserializer
+pub(crate) type LocalSchemas = Arc<ArcSwap<HashMap<u32, SchemaRef>>>;
+
+/// Options shared by every deserializer
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DeserializerConfig {
+    #[cfg(feature = "avro")]
+    pub(crate) reader_schema: Option<Arc<AvroSchema>>,
+    #[cfg(feature = "json")]
+    pub(crate) validate_json: bool,
+}
+
+#[derive(Clone)]
 pub struct Deserializer<'a> {
     pub(crate) registry: &'a SchemaRegistry,
+    pub(crate) config: DeserializerConfig,
 }
 
 impl<'a> Deserializer<'a> {
     pub async fn deserialize<D: DeserializeOwned>(&self, data: &[u8], format: Format) -> Result<D> {
         deserialize_uncached(self, data, format).await
     }
+
+    /// Checks each payload against its JSON schema before deserializing it, failing with
+    /// [`Error::JsonSchemaViolation`] if it doesn't conform
+    #[cfg(feature = "json")]
+    pub fn with_json_validation(mut self, validate: bool) -> Self {
+        self.config.validate_json = validate;
+        self
+    }
+
+    /// Decodes Avro payloads into `schema` rather than the schema they were written with
+    ///
+    /// Payloads written with an older or newer version of the schema then go through Avro schema
+    /// resolution, so missing fields are filled in with their defaults and removed fields are
+    /// dropped
+    #[cfg(feature = "avro")]
+    pub fn with_reader_schema(mut self, schema: AvroSchema) -> Self {
+        self.config.reader_schema = Some(Arc::new(schema));
+        self
+    }
+
+    /// Same as [`with_reader_schema`](Self::with_reader_schema), using the schema registered
+    /// under `details`
+    ///
+    /// If no version is set on `details`, the latest version at the time of calling is used
+    #[cfg(feature = "avro")]
+    pub async fn with_reader_schema_details(mut self, details: &SchemaDetails) -> Result<Self> {
+        self.config.reader_schema = Some(fetch_reader_schema(self.registry, details).await?);
+        Ok(self)
+    }
 }
 
 #[derive(Clone)]
 pub struct CachedDeserializer<'a> {
     pub(crate) registry: &'a SchemaRegistry,
-    pub(crate) schema: RwLock<Option<SchemaRef>>,
+    pub(crate) schemas: LocalSchemas,
+    pub(crate) config: DeserializerConfig,
 }
 
 impl<'a> CachedDeserializer<'a> {
     pub async fn deserialize<D: DeserializeOwned>(&self, data: &[u8], format: Format) -> Result<D> {
         deserialize_cached(self, data, format).await
     }
+
+    /// Checks each payload against its JSON schema before deserializing it, failing with
+    /// [`Error::JsonSchemaViolation`] if it doesn't conform
+    #[cfg(feature = "json")]
+    pub fn with_json_validation(mut self, validate: bool) -> Self {
+        self.config.validate_json = validate;
+        self
+    }
 }
 
 #[derive(Clone)]
 pub struct ArcDeserializer {
     pub(crate) registry: Arc<SchemaRegistry>,
+    pub(crate) config: DeserializerConfig,
 }
 
 impl ArcDeserializer {
     pub fn new(registry: Arc<SchemaRegistry>) -> Self {
-        Self { registry }
+        Self {
+            registry,
+            config: DeserializerConfig::default(),
+        }
     }
 
     pub async fn deserialize<D: DeserializeOwned>(&self, data: &[u8], format: Format) -> Result<D> {
         deserialize_uncached(self, data, format).await
     }
+
+    /// Checks each payload against its JSON schema before deserializing it, failing with
+    /// [`Error::JsonSchemaViolation`] if it doesn't conform
+    #[cfg(feature = "json")]
+    pub fn with_json_validation(mut self, validate: bool) -> Self {
+        self.config.validate_json = validate;
+        self
+    }
+
+    /// Decodes Avro payloads into `schema` rather than the schema they were written with
+    ///
+    /// Payloads written with an older or newer version of the schema then go through Avro schema
+    /// resolution, so missing fields are filled in with their defaults and removed fields are
+    /// dropped
+    #[cfg(feature = "avro")]
+    pub fn with_reader_schema(mut self, schema: AvroSchema) -> Self {
+        self.config.reader_schema = Some(Arc::new(schema));
+        self
+    }
+
+    /// Same as [`with_reader_schema`](Self::with_reader_schema), using the schema registered
+    /// under `details`
+    ///
+    /// If no version is set on `details`, the latest version at the time of calling is used
+    #[cfg(feature = "avro")]
+    pub async fn with_reader_schema_details(mut self, details: &SchemaDetails) -> Result<Self> {
+        self.config.reader_schema = Some(fetch_reader_schema(&self.registry, details).await?);
+        Ok(self)
+    }
 }
 
 #[derive(Clone)]
 pub struct ArcCachedDeserializer {
     pub(crate) registry: Arc<SchemaRegistry>,
-    pub(crate) schema: RwLock<Option<SchemaRef>>,
+    pub(crate) schemas: LocalSchemas,
+    pub(crate) config: DeserializerConfig,
 }
 
 impl ArcCachedDeserializer {
     pub fn new(registry: Arc<SchemaRegistry>) -> Self {
         Self {
             registry,
-            schema: RwLock::new(None),
+            schemas: LocalSchemas::default(),
+            config: DeserializerConfig::default(),
         }
     }
 
     pub async fn deserialize<D: DeserializeOwned>(&self, data: &[u8], format: Format) -> Result<D> {
         deserialize_cached(self, data, format).await
     }
+
+    /// Checks each payload against its JSON schema before deserializing it, failing with
+    /// [`Error::JsonSchemaViolation`] if it doesn't conform
+    #[cfg(feature = "json")]
+    pub fn with_json_validation(mut self, validate: bool) -> Self {
+        self.config.validate_json = validate;
+        self
+    }
 }
 
 trait DeserializeUncached {
     fn get_registry(&self) -> &SchemaRegistry;
+    fn get_config(&self) -> &DeserializerConfig;
 }
 
 impl DeserializeUncached for ArcDeserializer {
     fn get_registry(&self) -> &SchemaRegistry {
         &self.registry
     }
+
+    fn get_config(&self) -> &DeserializerConfig {
+        &self.config
+    }
 }
 
 impl<'a> DeserializeUncached for Deserializer<'a> {
     fn get_registry(&self) -> &SchemaRegistry {
-        &self.registry
+        self.registry
+    }
+
+    fn get_config(&self) -> &DeserializerConfig {
+        &self.config
     }
 }
 
 trait DeserializeCached {
-    fn get_schema(&self) -> &RwLock<Option<SchemaRef>>;
+    fn get_schemas(&self) -> &LocalSchemas;
     fn get_registry(&self) -> &SchemaRegistry;
+    fn get_config(&self) -> &DeserializerConfig;
 }
 
 impl DeserializeCached for ArcCachedDeserializer {
-    fn get_schema(&self) -> &RwLock<Option<SchemaRef>> {
-        &self.schema
+    fn get_schemas(&self) -> &LocalSchemas {
+        &self.schemas
     }
 
     fn get_registry(&self) -> &SchemaRegistry {
         &self.registry
     }
+
+    fn get_config(&self) -> &DeserializerConfig {
+        &self.config
+    }
 }
 
 impl<'a> DeserializeCached for CachedDeserializer<'a> {
-    fn get_schema(&self) -> &RwLock<Option<SchemaRef>> {
-        &self.schema
+    fn get_schemas(&self) -> &LocalSchemas {
+        &self.schemas
     }
 
     fn get_registry(&self) -> &SchemaRegistry {
-        &self.registry
+        self.registry
+    }
+
+    fn get_config(&self) -> &DeserializerConfig {
+        &self.config
     }
 }
 async fn deserialize_uncached<D: DeserializeOwned>(
@@ -119,12 +245,8 @@ async fn deserialize_uncached<D: DeserializeOwned>(
     let id = [data[1], data[2], data[3], data[4]];
     let id = u32::from_be_bytes(id);
     let raw_data = &data[5..];
-    match format {
-        Format::Avro => {
-            let schema_ref = this.get_registry().get_schema_by_id(id, format).await?;
-            deserialize_avro(&schema_ref, &raw_data)
-        }
-    }
+    let schema_ref = this.get_registry().get_schema_by_id(id, format).await?;
+    deserialize_with_schema(&schema_ref, raw_data, format, this.get_config())
 }
 
 async fn deserialize_cached<D: DeserializeOwned>(
@@ -141,28 +263,64 @@ async fn deserialize_cached<D: DeserializeOwned>(
     let id = [data[1], data[2], data[3], data[4]];
     let id = u32::from_be_bytes(id);
     let raw_data = &data[5..];
+    if let Some(schema_ref) = this.get_schemas().load().get(&id) {
+        return deserialize_with_schema(schema_ref, raw_data, format, this.get_config());
+    }
+    let schema_ref = this.get_registry().get_schema_by_id(id, format).await?;
+    this.get_schemas().rcu(|schemas| {
+        let mut schemas = HashMap::clone(schemas);
+        schemas.insert(id, schema_ref.clone());
+        schemas
+    });
+    deserialize_with_schema(&schema_ref, raw_data, format, this.get_config())
+}
+
+#[cfg(feature = "avro")]
+#[allow(irrefutable_let_patterns, unreachable_patterns)]
+async fn fetch_reader_schema(
+    registry: &SchemaRegistry,
+    details: &SchemaDetails,
+) -> Result<Arc<AvroSchema>> {
+    let schema_ref = registry.get_schema_by_subject(details).await?;
+    if let Schema::Avro(ref s) = &*schema_ref.schema {
+        Ok(Arc::clone(s))
+    } else {
+        Err(Error::IncorrectSchemaType(
+            "Avro".to_owned(),
+            schema_ref.schema.schema_type().to_string(),
+        ))
+    }
+}
+
+#[allow(unused_variables)]
+fn deserialize_with_schema<D: DeserializeOwned>(
+    schema_ref: &SchemaRef,
+    data: &[u8],
+    format: Format,
+    config: &DeserializerConfig,
+) -> Result<D> {
     match format {
-        Format::Avro => loop {
-            {
-                let handle = this.get_schema().read().await;
-                if let Some(ref schema_ref) = *handle {
-                    return deserialize_avro(schema_ref, &raw_data);
-                }
-            }
-            {
-                if let Ok(mut handle) = this.get_schema().try_write() {
-                    let schema_ref = this.get_registry().get_schema_by_id(id, format).await?;
-                    *handle = Some(schema_ref);
-                }
-            }
-        },
-    }
-}
-
-fn deserialize_avro<D: DeserializeOwned>(schema_ref: &SchemaRef, data: &[u8]) -> Result<D> {
+        #[cfg(feature = "avro")]
+        Format::Avro => deserialize_avro(schema_ref, data, config.reader_schema.as_deref()),
+        #[cfg(not(feature = "avro"))]
+        Format::Avro => Err(Error::AvroNotEnabled),
+        #[cfg(feature = "proto")]
+        Format::Protobuf => deserialize_protobuf(schema_ref, data),
+        #[cfg(feature = "json")]
+        Format::Json => deserialize_json(schema_ref, data, config.validate_json),
+    }
+}
+
+#[cfg(feature = "avro")]
+#[allow(irrefutable_let_patterns, unreachable_patterns)]
+fn deserialize_avro<D: DeserializeOwned>(
+    schema_ref: &SchemaRef,
+    data: &[u8],
+    reader_schema: Option<&AvroSchema>,
+) -> Result<D> {
     if let Schema::Avro(ref s) = &*schema_ref.schema {
         let mut reader = Cursor::new(data);
-        let value = avro_rs::from_avro_datum(&s, &mut reader, None)?;
+        let value = avro_rs::from_avro_datum(s, &mut reader, reader_schema)?;
         let final_value = avro_rs::from_value::<D>(&value)?;
         Ok(final_value)
     } else {
@@ -172,3 +330,33 @@ fn deserialize_avro<D: DeserializeOwned>(schema_ref: &SchemaRef, data: &[u8]) ->
         ))
     }
 }
+
+#[cfg(feature = "proto")]
+#[allow(irrefutable_let_patterns)]
+fn deserialize_protobuf<D: DeserializeOwned>(schema_ref: &SchemaRef, data: &[u8]) -> Result<D> {
+    if let Schema::Protobuf(ref s) = &*schema_ref.schema {
+        proto::deserialize(s, data)
+    } else {
+        Err(Error::IncorrectSchemaType(
+            "Protobuf".to_owned(),
+            schema_ref.schema.schema_type().to_string(),
+        ))
+    }
+}
+
+#[cfg(feature = "json")]
+#[allow(irrefutable_let_patterns)]
+fn deserialize_json<D: DeserializeOwned>(
+    schema_ref: &SchemaRef,
+    data: &[u8],
+    validate: bool,
+) -> Result<D> {
+    if let Schema::Json(ref s) = &*schema_ref.schema {
+        json::deserialize(s, data, validate)
+    } else {
+        Err(Error::IncorrectSchemaType(
+            "Json".to_owned(),
+            schema_ref.schema.schema_type().to_string(),
+        ))
+    }
+}
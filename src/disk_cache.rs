@@ -0,0 +1,72 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::cache::RawSchema;
+
+/// A directory of raw schemas, one `{id}.json` file per schema id
+///
+/// Everything needed to parse a schema is kept in its file, including the raw schemas it
+/// references, so that the schemas can be loaded without the registry being reachable. The cache
+/// is best effort, files that can't be read or written are skipped rather than treated as errors.
+#[derive(Debug, Clone)]
+pub(crate) struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Reads every schema in the directory, skipping any files that aren't a cached schema
+    pub(crate) fn load(&self) -> Vec<(u32, RawSchema)> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "json" {
+                    return None;
+                }
+                let id = path.file_stem()?.to_str()?.parse().ok()?;
+                let contents = fs::read(&path).ok()?;
+                let raw = serde_json::from_slice(&contents).ok()?;
+                Some((id, raw))
+            })
+            .collect()
+    }
+
+    /// Writes the schema on Tokio's blocking thread pool, so the file system never holds up the
+    /// executor
+    pub(crate) async fn store(&self, id: u32, raw: Arc<RawSchema>) {
+        let disk_cache = self.clone();
+        let _ = tokio::task::spawn_blocking(move || disk_cache.store_blocking(id, &raw)).await;
+    }
+
+    /// Writes the schema to a temporary file first, so a crash part way through never leaves a
+    /// truncated schema behind
+    ///
+    /// The temporary file's name is unique, so processes sharing the directory can't write over
+    /// each other's half written files.
+    fn store_blocking(&self, id: u32, raw: &RawSchema) {
+        let contents = match serde_json::to_vec(raw) {
+            Ok(contents) => contents,
+            Err(_) => return,
+        };
+        let tmp = self.dir.join(format!(
+            "{}.json.{}-{:016x}.tmp",
+            id,
+            std::process::id(),
+            fastrand::u64(..)
+        ));
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&tmp, contents))
+            .and_then(|_| fs::rename(&tmp, self.dir.join(format!("{}.json", id))));
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+}
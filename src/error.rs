@@ -1,12 +1,64 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use std::fmt;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[cfg(feature = "avro")]
     #[error(transparent)]
     Avro(#[from] avro_rs::Error),
 
+    #[cfg(not(feature = "avro"))]
+    #[error("Avro schemas can only be used with the `avro` feature enabled")]
+    AvroNotEnabled,
+
+    #[cfg(feature = "proto")]
+    #[error(transparent)]
+    ProtobufSchema(#[from] protox::Error),
+
+    #[cfg(feature = "proto")]
+    #[error(transparent)]
+    ProtobufDecode(#[from] prost_reflect::prost::DecodeError),
+
+    #[cfg(feature = "proto")]
+    #[error("Could not find a message named {0} in the protobuf schema")]
+    MessageNotFound(String),
+
+    #[cfg(feature = "proto")]
+    #[error("The message indexes in the data don't point to a message in the protobuf schema")]
+    InvalidMessageIndexes,
+
+    #[cfg(feature = "json")]
+    #[error("The JSON schema could not be compiled: {0}")]
+    InvalidJsonSchema(String),
+
+    #[cfg(feature = "json")]
+    #[error("The data does not conform to the JSON schema: {}", .0.join("; "))]
+    JsonSchemaViolation(Vec<String>),
+
+    #[error("The schema is not compatible with the registered version: {}", .0.join("; "))]
+    IncompatibleSchema(Vec<String>),
+
     #[error(transparent)]
     Http(#[from] reqwest::Error),
 
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Could not get a token to authenticate with the registry: {0}")]
+    TokenProvider(#[source] crate::auth::TokenError),
+
+    /// The failure of a registry request that was made once on behalf of several callers
+    #[error(transparent)]
+    Shared(std::sync::Arc<Error>),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
     #[error("Expected to recieve a Schema ID from the registry but found nothing")]
     IDNotReturned,
 
@@ -25,9 +77,258 @@ pub enum Error {
     #[error("Either the subject or the ID must be a valid value to find a schema")]
     InvalidInput,
 
-    #[error("Received error from the schema registry, error code {error_code}: {message}")]
-    SchemaRegistryError { error_code: u32, message: String },
+    #[error("Received error from the schema registry for {path} ({status}), error code {error_code}: {message}")]
+    SchemaRegistryError {
+        status: StatusCode,
+        path: String,
+        error_code: RegistryErrorCode,
+        message: String,
+    },
+
+    /// The registry rejected the request's credentials, or they don't allow the request
+    #[error("The schema registry refused access to {path} ({status}): {message}")]
+    Unauthorized {
+        status: StatusCode,
+        path: String,
+        message: String,
+    },
+
+    /// None of the registry URLs could be connected to in time
+    #[error("Could not reach the schema registry to request {path}")]
+    RegistryUnreachable {
+        path: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// The registry responded with something other than what was asked for or one of its errors,
+    /// such as an error page from a proxy in front of it. `body` is cut short if it's long.
+    #[error("Unexpected response from the schema registry for {path} ({status}): {body}")]
+    UnexpectedResponse {
+        status: StatusCode,
+        path: String,
+        body: String,
+    },
 
     #[error("An unexpected error occurred")]
     UnexpectedError,
 }
+
+impl Error {
+    /// The error code the registry responded with, if this error came from the registry
+    pub fn registry_error_code(&self) -> Option<RegistryErrorCode> {
+        match self {
+            Self::SchemaRegistryError { error_code, .. } => Some(*error_code),
+            Self::Shared(error) => error.registry_error_code(),
+            _ => None,
+        }
+    }
+}
+
+/// The error codes documented by the schema registry
+///
+/// Error codes are the HTTP status the registry responded with, sometimes followed by two more
+/// digits to narrow it down. Any code that isn't documented is kept as [`Other`](Self::Other).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(from = "u32")]
+pub enum RegistryErrorCode {
+    /// 40401
+    SubjectNotFound,
+    /// 40402
+    VersionNotFound,
+    /// 40403
+    SchemaNotFound,
+    /// 40404, the subject has been soft deleted
+    SubjectSoftDeleted,
+    /// 40405, the subject has to be soft deleted before it can be permanently deleted
+    SubjectNotSoftDeleted,
+    /// 40406, the version has been soft deleted
+    VersionSoftDeleted,
+    /// 40407, the version has to be soft deleted before it can be permanently deleted
+    VersionNotSoftDeleted,
+    /// 40408, the subject has no compatibility level of its own
+    SubjectCompatibilityNotConfigured,
+    /// 40409, the subject has no mode of its own
+    SubjectModeNotConfigured,
+    /// 409, the schema isn't compatible with the versions already registered under the subject
+    IncompatibleSchema,
+    /// 42201
+    InvalidSchema,
+    /// 42202
+    InvalidVersion,
+    /// 42203
+    InvalidCompatibilityLevel,
+    /// 42204
+    InvalidMode,
+    /// 42205, the registry's mode doesn't allow the operation
+    OperationNotPermitted,
+    /// 42206, the schema is referenced by another schema so it can't be deleted
+    ReferenceExists,
+    /// 50001
+    BackendStoreError,
+    /// 50002
+    OperationTimedOut,
+    /// 50003, the registry couldn't forward the request to the primary registry
+    ForwardingError,
+    Other(u32),
+}
+
+impl RegistryErrorCode {
+    /// The numeric error code, as the registry sent it
+    pub fn code(self) -> u32 {
+        match self {
+            Self::SubjectNotFound => 40401,
+            Self::VersionNotFound => 40402,
+            Self::SchemaNotFound => 40403,
+            Self::SubjectSoftDeleted => 40404,
+            Self::SubjectNotSoftDeleted => 40405,
+            Self::VersionSoftDeleted => 40406,
+            Self::VersionNotSoftDeleted => 40407,
+            Self::SubjectCompatibilityNotConfigured => 40408,
+            Self::SubjectModeNotConfigured => 40409,
+            Self::IncompatibleSchema => 409,
+            Self::InvalidSchema => 42201,
+            Self::InvalidVersion => 42202,
+            Self::InvalidCompatibilityLevel => 42203,
+            Self::InvalidMode => 42204,
+            Self::OperationNotPermitted => 42205,
+            Self::ReferenceExists => 42206,
+            Self::BackendStoreError => 50001,
+            Self::OperationTimedOut => 50002,
+            Self::ForwardingError => 50003,
+            Self::Other(code) => code,
+        }
+    }
+
+    /// Whether the subject, version or schema that was asked for doesn't exist, including when it
+    /// has only been soft deleted
+    pub fn is_not_found(self) -> bool {
+        matches!(
+            self,
+            Self::SubjectNotFound
+                | Self::VersionNotFound
+                | Self::SchemaNotFound
+                | Self::SubjectSoftDeleted
+                | Self::VersionSoftDeleted
+                | Self::SubjectCompatibilityNotConfigured
+                | Self::SubjectModeNotConfigured
+        ) || self.status() == 404
+    }
+
+    /// Whether the schema was rejected for not being compatible with the existing versions
+    pub fn is_incompatible(self) -> bool {
+        self.status() == 409
+    }
+
+    /// Whether the same request might succeed if it is made again, because the registry was
+    /// overloaded or failed internally rather than rejecting the request itself
+    pub fn is_retryable(self) -> bool {
+        let status = self.status();
+        status == 429 || (500..600).contains(&status)
+    }
+
+    /// The HTTP status the code is based on
+    fn status(self) -> u32 {
+        let code = self.code();
+        if code >= 1000 {
+            code / 100
+        } else {
+            code
+        }
+    }
+}
+
+impl From<u32> for RegistryErrorCode {
+    fn from(code: u32) -> Self {
+        match code {
+            40401 => Self::SubjectNotFound,
+            40402 => Self::VersionNotFound,
+            40403 => Self::SchemaNotFound,
+            40404 => Self::SubjectSoftDeleted,
+            40405 => Self::SubjectNotSoftDeleted,
+            40406 => Self::VersionSoftDeleted,
+            40407 => Self::VersionNotSoftDeleted,
+            40408 => Self::SubjectCompatibilityNotConfigured,
+            40409 => Self::SubjectModeNotConfigured,
+            409 => Self::IncompatibleSchema,
+            42201 => Self::InvalidSchema,
+            42202 => Self::InvalidVersion,
+            42203 => Self::InvalidCompatibilityLevel,
+            42204 => Self::InvalidMode,
+            42205 => Self::OperationNotPermitted,
+            42206 => Self::ReferenceExists,
+            50001 => Self::BackendStoreError,
+            50002 => Self::OperationTimedOut,
+            50003 => Self::ForwardingError,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for RegistryErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RegistryErrorCode::{self, *};
+
+    const DOCUMENTED: [(u32, RegistryErrorCode); 19] = [
+        (40401, SubjectNotFound),
+        (40402, VersionNotFound),
+        (40403, SchemaNotFound),
+        (40404, SubjectSoftDeleted),
+        (40405, SubjectNotSoftDeleted),
+        (40406, VersionSoftDeleted),
+        (40407, VersionNotSoftDeleted),
+        (40408, SubjectCompatibilityNotConfigured),
+        (40409, SubjectModeNotConfigured),
+        (409, IncompatibleSchema),
+        (42201, InvalidSchema),
+        (42202, InvalidVersion),
+        (42203, InvalidCompatibilityLevel),
+        (42204, InvalidMode),
+        (42205, OperationNotPermitted),
+        (42206, ReferenceExists),
+        (50001, BackendStoreError),
+        (50002, OperationTimedOut),
+        (50003, ForwardingError),
+    ];
+
+    #[test]
+    fn documented_codes_round_trip() {
+        for (code, error_code) in DOCUMENTED {
+            assert_eq!(RegistryErrorCode::from(code), error_code);
+            assert_eq!(error_code.code(), code);
+        }
+    }
+
+    #[test]
+    fn other_codes_are_kept_as_they_are() {
+        for code in [0, 400, 429, 40410, 50099] {
+            assert_eq!(RegistryErrorCode::from(code), Other(code));
+            assert_eq!(Other(code).code(), code);
+        }
+    }
+
+    #[test]
+    fn server_errors_and_rate_limits_are_retryable() {
+        for code in [50001, 50002, 50003, 429, 500, 50099] {
+            assert!(RegistryErrorCode::from(code).is_retryable(), "{}", code);
+        }
+        for code in [40401, 409, 42201, 42205] {
+            assert!(!RegistryErrorCode::from(code).is_retryable(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn conflicts_are_incompatible() {
+        assert!(RegistryErrorCode::from(409).is_incompatible());
+        assert!(RegistryErrorCode::from(40901).is_incompatible());
+        for code in [40401, 42201, 50001] {
+            assert!(!RegistryErrorCode::from(code).is_incompatible(), "{}", code);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use jsonschema::{Resource, Validator};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use std::fmt;
+use std::io;
+
+use crate::schema::ResolvedReference;
+use crate::{Error, Result};
+
+/// Relative reference names are resolved by the validator against this base
+const DEFAULT_BASE_URI: &str = "json-schema:///";
+
+/// A parsed JSON Schema, along with a validator compiled from it and everything it references
+pub struct JsonSchema {
+    schema: Value,
+    validator: Validator,
+}
+
+impl JsonSchema {
+    /// Each reference is made available to `$ref` under its reference name
+    pub(crate) fn parse(schema: &str, references: &[ResolvedReference]) -> Result<Self> {
+        let schema: Value = serde_json::from_str(schema)?;
+        let mut options = jsonschema::options();
+        for reference in references {
+            let contents = serde_json::from_str(&reference.schema)?;
+            let resource = Resource::from_contents(contents)
+                .map_err(|e| Error::InvalidJsonSchema(e.to_string()))?;
+            let uri = if reference.name.contains(':') {
+                reference.name.clone()
+            } else {
+                format!("{}{}", DEFAULT_BASE_URI, reference.name)
+            };
+            options = options.with_resource(uri, resource);
+        }
+        let validator = options
+            .build(&schema)
+            .map_err(|e| Error::InvalidJsonSchema(e.to_string()))?;
+        Ok(Self { schema, validator })
+    }
+
+    fn validate(&self, value: &Value) -> Result<()> {
+        let violations: Vec<String> = self
+            .validator
+            .iter_errors(value)
+            .map(|e| e.to_string())
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::JsonSchemaViolation(violations))
+        }
+    }
+}
+
+impl fmt::Debug for JsonSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonSchema")
+            .field("schema", &self.schema)
+            .finish()
+    }
+}
+
+impl PartialEq for JsonSchema {
+    fn eq(&self, other: &Self) -> bool {
+        self.schema == other.schema
+    }
+}
+
+/// Encodes the data as UTF-8 JSON, optionally checking it against the schema first
+pub(crate) fn serialize<S: Serialize, W: io::Write>(
+    schema: &JsonSchema,
+    data: S,
+    validate: bool,
+    writer: W,
+) -> Result<()> {
+    if !validate {
+        return Ok(serde_json::to_writer(writer, &data)?);
+    }
+    let value = serde_json::to_value(data)?;
+    schema.validate(&value)?;
+    Ok(serde_json::to_writer(writer, &value)?)
+}
+
+/// Decodes the UTF-8 JSON into the type, optionally checking it against the schema first
+pub(crate) fn deserialize<D: DeserializeOwned>(
+    schema: &JsonSchema,
+    data: &[u8],
+    validate: bool,
+) -> Result<D> {
+    if !validate {
+        return Ok(serde_json::from_slice(data)?);
+    }
+    let value: Value = serde_json::from_slice(data)?;
+    schema.validate(&value)?;
+    Ok(serde_json::from_value(value)?)
+}
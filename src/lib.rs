@@ -1,15 +1,42 @@
+#[cfg(not(any(feature = "avro", feature = "proto", feature = "json")))]
+compile_error!("At least one of the `avro`, `proto` or `json` features has to be enabled");
+
+mod auth;
+mod builder;
+mod cache;
+mod compatibility;
 mod deserializer;
+mod disk_cache;
 mod error;
+#[cfg(feature = "json")]
+mod json;
+mod mode;
+mod nodes;
+#[cfg(feature = "proto")]
+mod proto;
+mod retry;
 mod schema;
 mod schema_registry;
 mod serializer;
+mod single_flight;
 
-pub use error::Error;
+pub use auth::{TokenError, TokenProvider};
+pub use builder::SchemaRegistryBuilder;
+pub use cache::{CacheSnapshot, CacheStats};
+pub use compatibility::{CompatibilityCheck, CompatibilityLevel};
+pub use error::{Error, RegistryErrorCode};
+pub use mode::RegistryMode;
+pub use nodes::FailoverStrategy;
+pub use retry::RetryPolicy;
 pub type Result<T> = std::result::Result<T, Error>;
 pub use deserializer::{ArcCachedDeserializer, ArcDeserializer, CachedDeserializer, Deserializer};
-pub use schema::{Format, SchemaDetails, SubjectNamingStrategy};
-pub use schema_registry::SchemaRegistry;
-pub use serializer::Serializer;
+pub use schema::{
+    Format, RegisteredReference, SchemaDetails, SchemaReference, SubjectNamingStrategy,
+};
+pub use schema_registry::{SchemaRef, SchemaRegistry, SubjectVersion};
+pub use serializer::{Serializer, SerializerMode};
 
 #[cfg(feature = "avro")]
 pub use avro_rs as avro;
+#[cfg(feature = "proto")]
+pub use prost_reflect as protobuf;
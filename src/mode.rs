@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+/// Controls which operations the registry will accept, either globally or for a single subject
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RegistryMode {
+    /// Schemas can be read and registered as normal
+    #[serde(rename = "READWRITE")]
+    ReadWrite,
+    /// Schemas can be read, but nothing can be registered or deleted
+    #[serde(rename = "READONLY")]
+    ReadOnly,
+    /// The same as [`RegistryMode::ReadOnly`], however it also can't be overridden at the subject
+    /// level
+    #[serde(rename = "READONLY_OVERRIDE")]
+    ReadOnlyOverride,
+    /// Schemas can be registered with an explicit ID and version, which is used when migrating
+    /// schemas between registries
+    ///
+    /// The registry will only allow this mode to be set on an empty registry (or subject) unless
+    /// it's forced
+    #[serde(rename = "IMPORT")]
+    Import,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ModeBody {
+    pub(crate) mode: RegistryMode,
+}
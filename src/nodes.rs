@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The order registry URLs are tried in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailoverStrategy {
+    /// Always start with the first URL, only moving on to the next one when it fails
+    #[default]
+    Ordered,
+    /// Start each request with the next URL along, spreading requests over all of them
+    RoundRobin,
+}
+
+/// The registry URLs, along with which of them have recently failed
+#[derive(Debug)]
+pub(crate) struct Nodes {
+    urls: Vec<String>,
+    strategy: FailoverStrategy,
+    cooldown: Duration,
+    next: AtomicUsize,
+    unhealthy_until: Mutex<Vec<Option<Instant>>>,
+}
+
+impl Nodes {
+    pub(crate) fn new(urls: Vec<String>, strategy: FailoverStrategy, cooldown: Duration) -> Self {
+        let unhealthy_until = Mutex::new(vec![None; urls.len()]);
+        Self {
+            urls,
+            strategy,
+            cooldown,
+            next: AtomicUsize::new(0),
+            unhealthy_until,
+        }
+    }
+
+    pub(crate) fn url(&self, node: usize) -> &str {
+        &self.urls[node]
+    }
+
+    /// Every node, in the order they should be tried for the next request
+    ///
+    /// Nodes that have failed recently are still included, after all of the healthy ones, so that
+    /// a request is never refused outright just because every node has failed at some point
+    pub(crate) fn order(&self) -> Vec<usize> {
+        let len = self.urls.len();
+        let start = match self.strategy {
+            FailoverStrategy::Ordered => 0,
+            FailoverStrategy::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % len.max(1),
+        };
+        let now = Instant::now();
+        let unhealthy_until = self.lock();
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) = (0..len)
+            .map(|i| (start + i) % len)
+            .partition(|&node| unhealthy_until[node].is_none_or(|until| until <= now));
+        healthy.into_iter().chain(unhealthy).collect()
+    }
+
+    pub(crate) fn mark_unhealthy(&self, node: usize) {
+        self.lock()[node] = Some(Instant::now() + self.cooldown);
+    }
+
+    pub(crate) fn mark_healthy(&self, node: usize) {
+        self.lock()[node] = None;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Option<Instant>>> {
+        self.unhealthy_until
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes(strategy: FailoverStrategy, cooldown: Duration) -> Nodes {
+        let urls = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        Nodes::new(urls, strategy, cooldown)
+    }
+
+    #[test]
+    fn ordered_nodes_always_start_from_the_first() {
+        let nodes = nodes(FailoverStrategy::Ordered, Duration::from_secs(30));
+        assert_eq!(nodes.order(), vec![0, 1, 2]);
+        assert_eq!(nodes.order(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn round_robin_nodes_start_from_the_next_one_along() {
+        let nodes = nodes(FailoverStrategy::RoundRobin, Duration::from_secs(30));
+        assert_eq!(nodes.order(), vec![0, 1, 2]);
+        assert_eq!(nodes.order(), vec![1, 2, 0]);
+        assert_eq!(nodes.order(), vec![2, 0, 1]);
+        assert_eq!(nodes.order(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn unhealthy_nodes_are_tried_last_until_their_cooldown_is_over() {
+        let unhealthy = nodes(FailoverStrategy::Ordered, Duration::from_secs(30));
+        unhealthy.mark_unhealthy(0);
+        unhealthy.mark_unhealthy(1);
+        assert_eq!(unhealthy.order(), vec![2, 0, 1]);
+        unhealthy.mark_healthy(0);
+        assert_eq!(unhealthy.order(), vec![0, 2, 1]);
+
+        let cooled_down = nodes(FailoverStrategy::Ordered, Duration::ZERO);
+        cooled_down.mark_unhealthy(0);
+        assert_eq!(cooled_down.order(), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,198 @@
+use prost_reflect::prost::bytes::BufMut;
+use prost_reflect::prost::Message;
+use prost_reflect::{
+    DeserializeOptions, DynamicMessage, FileDescriptor, MessageDescriptor, SerializeOptions,
+};
+use protox::file::{ChainFileResolver, File, FileResolver, GoogleFileResolver};
+use protox::Compiler;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use std::collections::HashMap;
+
+use crate::schema::{ResolvedReference, SubjectNamingStrategy};
+use crate::{Error, Result, SchemaDetails};
+
+/// The registry doesn't store a file name for the schema itself, so it's compiled under this one
+const ROOT_FILE_NAME: &str = "confluent_schema_registry/root.proto";
+
+/// A parsed `.proto` file, along with everything it imports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtobufSchema {
+    file: FileDescriptor,
+}
+
+impl ProtobufSchema {
+    /// Each reference is made available to the schema's imports under its reference name, any
+    /// of the well known `google/protobuf/*.proto` types are always available
+    pub(crate) fn parse(schema: &str, references: &[ResolvedReference]) -> Result<Self> {
+        let mut files: HashMap<&str, &str> = references
+            .iter()
+            .map(|r| (r.name.as_str(), r.schema.as_str()))
+            .collect();
+        files.insert(ROOT_FILE_NAME, schema);
+
+        let mut resolver = ChainFileResolver::new();
+        resolver.add(SourceFileResolver(
+            files
+                .into_iter()
+                .map(|(name, source)| (name.to_owned(), source.to_owned()))
+                .collect(),
+        ));
+        resolver.add(GoogleFileResolver::new());
+
+        let mut compiler = Compiler::with_file_resolver(resolver);
+        compiler.open_file(ROOT_FILE_NAME)?;
+        let file = compiler
+            .descriptor_pool()
+            .get_file_by_name(ROOT_FILE_NAME)
+            .ok_or(Error::UnexpectedError)?;
+        Ok(Self { file })
+    }
+
+    /// Finds the message that should be serialized by default for these schema details
+    ///
+    /// If the subject naming strategy names a record then that message is used, otherwise it's
+    /// the first message declared in the schema
+    pub(crate) fn default_message(&self, details: &SchemaDetails) -> Result<MessageDescriptor> {
+        match &details.subject_naming_strategy {
+            SubjectNamingStrategy::RecordNameStrategy { message_type_name }
+            | SubjectNamingStrategy::TopicRecordNameStrategy {
+                message_type_name, ..
+            } => self.message_by_name(message_type_name),
+            _ => self
+                .file
+                .messages()
+                .next()
+                .ok_or_else(|| Error::MessageNotFound("<first message>".to_owned())),
+        }
+    }
+
+    /// Finds a message by its fully qualified name, the name may optionally start with a `.`
+    pub(crate) fn message_by_name(&self, name: &str) -> Result<MessageDescriptor> {
+        let name = name.trim_start_matches('.');
+        self.file
+            .parent_pool()
+            .get_message_by_name(name)
+            .filter(|message| message.parent_file() == self.file)
+            .ok_or_else(|| Error::MessageNotFound(name.to_owned()))
+    }
+
+    /// Follows the message indexes down through the (possibly nested) messages in the schema
+    fn message_by_indexes(&self, indexes: &[i32]) -> Result<MessageDescriptor> {
+        let mut indexes = indexes.iter().map(|i| *i as usize);
+        let mut message = indexes
+            .next()
+            .and_then(|i| self.file.messages().nth(i))
+            .ok_or(Error::InvalidMessageIndexes)?;
+        for i in indexes {
+            let child = message.child_messages().nth(i);
+            message = child.ok_or(Error::InvalidMessageIndexes)?;
+        }
+        Ok(message)
+    }
+}
+
+struct SourceFileResolver(HashMap<String, String>);
+
+impl FileResolver for SourceFileResolver {
+    fn open_file(&self, name: &str) -> std::result::Result<File, protox::Error> {
+        match self.0.get(name) {
+            Some(source) => File::from_source(name, source),
+            None => Err(protox::Error::file_not_found(name)),
+        }
+    }
+}
+
+/// Encodes the data as the message, prefixed with the message indexes that Confluent's wire
+/// format uses to identify which message in the schema was used
+pub(crate) fn serialize<S: Serialize, B: BufMut>(
+    message: &MessageDescriptor,
+    data: S,
+    bytes: &mut B,
+) -> Result<()> {
+    let value = serde_json::to_value(data)?;
+    let dynamic_message = DynamicMessage::deserialize_with_options(
+        message.clone(),
+        value,
+        &DeserializeOptions::new(),
+    )?;
+    encode_message_indexes(&message_indexes(message), bytes);
+    dynamic_message.encode_raw(bytes);
+    Ok(())
+}
+
+/// Decodes the message indexes from the front of the data, followed by the message they point to
+///
+/// The message is mapped into the type using the protobuf field names, and 64 bit integers are
+/// kept as numbers
+pub(crate) fn deserialize<D: DeserializeOwned>(schema: &ProtobufSchema, data: &[u8]) -> Result<D> {
+    let (indexes, data) = decode_message_indexes(data)?;
+    let message = schema.message_by_indexes(&indexes)?;
+    let dynamic_message = DynamicMessage::decode(message, data)?;
+    let options = SerializeOptions::new()
+        .use_proto_field_name(true)
+        .stringify_64_bit_integers(false)
+        .skip_default_fields(false);
+    let value = dynamic_message.serialize_with_options(serde_json::value::Serializer, &options)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// The index of the message within its parent, for every level of nesting from the top of the
+/// file down
+fn message_indexes(message: &MessageDescriptor) -> Vec<i32> {
+    // The path alternates between the field number of the list the message is declared in, and
+    // its index within that list
+    message.path().chunks(2).map(|p| p[1]).collect()
+}
+
+/// Writes the indexes as a zig-zag varint array, the common case of the first message in the file
+/// is shortened to a single `0`
+fn encode_message_indexes<B: BufMut>(indexes: &[i32], bytes: &mut B) {
+    if indexes == [0] {
+        bytes.put_u8(0);
+        return;
+    }
+    write_zigzag_varint(indexes.len() as i32, bytes);
+    for index in indexes {
+        write_zigzag_varint(*index, bytes);
+    }
+}
+
+fn decode_message_indexes(data: &[u8]) -> Result<(Vec<i32>, &[u8])> {
+    let (count, mut data) = read_zigzag_varint(data)?;
+    if count == 0 {
+        return Ok((vec![0], data));
+    }
+    if count < 0 || count as usize > data.len() {
+        return Err(Error::InvalidMessageIndexes);
+    }
+    let mut indexes = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (index, rest) = read_zigzag_varint(data)?;
+        indexes.push(index);
+        data = rest;
+    }
+    Ok((indexes, data))
+}
+
+fn write_zigzag_varint<B: BufMut>(value: i32, bytes: &mut B) {
+    let mut value = ((value << 1) ^ (value >> 31)) as u32;
+    while value >= 0x80 {
+        bytes.put_u8((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.put_u8(value as u8);
+}
+
+fn read_zigzag_varint(data: &[u8]) -> Result<(i32, &[u8])> {
+    let mut value = 0u32;
+    for (i, byte) in data.iter().enumerate().take(5) {
+        value |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            let value = ((value >> 1) as i32) ^ -((value & 1) as i32);
+            return Ok((value, &data[i + 1..]));
+        }
+    }
+    Err(Error::InvalidMessageIndexes)
+}
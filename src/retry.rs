@@ -0,0 +1,199 @@
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::io;
+use std::time::Duration;
+
+/// How requests to the registry are retried when they fail with what looks like a transient error
+///
+/// A request is retried when the registry can't be reached, the connection is reset, or the
+/// registry responds with one of the `retryable_statuses`. Between attempts the delay doubles,
+/// starting from `base_delay` and never exceeding `max_delay`. When the registry responds with a
+/// `Retry-After` header (in seconds), that is waited for instead, still capped at `max_delay`.
+///
+/// ```
+/// # use confluent_schema_registry::RetryPolicy;
+/// # use std::time::Duration;
+/// let policy = RetryPolicy {
+///     max_attempts: 5,
+///     max_delay: Duration::from_secs(30),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The total number of times a request is made, including the first, `1` disables retries
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Randomizes each delay to somewhere between zero and its full length, so that clients which
+    /// failed at the same time don't all retry at the same time
+    pub jitter: bool,
+    pub retryable_statuses: Vec<StatusCode>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+            retryable_statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retries, which is what a registry does unless it is given a policy
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn should_retry_response(&self, response: &Response) -> bool {
+        self.retryable_statuses.contains(&response.status())
+    }
+
+    pub(crate) fn should_retry_error(&self, error: &reqwest::Error) -> bool {
+        if error.is_connect() || error.is_timeout() {
+            return true;
+        }
+        let mut source = error.source();
+        while let Some(e) = source {
+            if let Some(e) = e.downcast_ref::<io::Error>() {
+                return matches!(
+                    e.kind(),
+                    io::ErrorKind::ConnectionReset
+                        | io::ErrorKind::ConnectionAborted
+                        | io::ErrorKind::BrokenPipe
+                        | io::ErrorKind::UnexpectedEof
+                );
+            }
+            source = e.source();
+        }
+        false
+    }
+
+    /// How long to wait after the given attempt (starting at 1) failed
+    pub(crate) fn delay(&self, attempt: u32, response: Option<&Response>) -> Duration {
+        if let Some(retry_after) = response.and_then(retry_after) {
+            return retry_after.min(self.max_delay);
+        }
+        let backoff = self
+            .base_delay
+            .checked_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        if self.jitter {
+            let millis = u64::try_from(backoff.as_millis()).unwrap_or(u64::MAX);
+            Duration::from_millis(fastrand::u64(..=millis))
+        } else {
+            backoff
+        }
+    }
+}
+
+/// Only the delay in seconds form of the header is understood, HTTP dates are ignored
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            jitter: false,
+            ..Default::default()
+        }
+    }
+
+    fn response(status: u16, retry_after: Option<&str>) -> Response {
+        let mut response = http::Response::builder().status(status);
+        if let Some(retry_after) = retry_after {
+            response = response.header(RETRY_AFTER, retry_after);
+        }
+        response.body("").unwrap().into()
+    }
+
+    #[test]
+    fn the_delay_doubles_after_each_attempt() {
+        let policy = policy();
+        let delays: Vec<_> = (1..=4).map(|attempt| policy.delay(attempt, None)).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(400),
+                Duration::from_millis(800),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_delay_is_capped_at_the_max_delay() {
+        let policy = policy();
+        assert_eq!(policy.delay(5, None), Duration::from_secs(1));
+        assert_eq!(policy.delay(u32::MAX, None), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_keeps_the_delay_within_the_backoff() {
+        let policy = RetryPolicy {
+            jitter: true,
+            ..policy()
+        };
+        for _ in 0..100 {
+            assert!(policy.delay(3, None) <= Duration::from_millis(400));
+        }
+    }
+
+    #[test]
+    fn retry_after_overrides_the_backoff() {
+        let policy = policy();
+        let retry_after = response(429, Some("0"));
+        assert_eq!(policy.delay(3, Some(&retry_after)), Duration::ZERO);
+        let retry_after = response(503, Some(" 60 "));
+        assert_eq!(policy.delay(1, Some(&retry_after)), Duration::from_secs(1));
+        // Dates aren't understood, so the backoff is used instead
+        let retry_after = response(503, Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert_eq!(
+            policy.delay(2, Some(&retry_after)),
+            Duration::from_millis(200)
+        );
+    }
+
+    #[test]
+    fn only_the_retryable_statuses_are_retried() {
+        let policy = RetryPolicy::default();
+        for status in [429, 500, 502, 503, 504] {
+            assert!(policy.should_retry_response(&response(status, None)));
+        }
+        for status in [200, 400, 401, 404, 409, 422, 501] {
+            assert!(!policy.should_retry_response(&response(status, None)));
+        }
+    }
+}
@@ -1,9 +1,14 @@
+#[cfg(feature = "avro")]
 use avro_rs::Schema as AvroSchema;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use std::sync::Arc;
 
-use crate::Result;
+#[cfg(feature = "json")]
+use crate::json::JsonSchema;
+#[cfg(feature = "proto")]
+use crate::proto::ProtobufSchema;
+use crate::{Error, Result};
 
 #[derive(Debug, Clone)]
 pub struct SchemaDetails {
@@ -12,32 +17,75 @@ pub struct SchemaDetails {
     pub subject_naming_strategy: SubjectNamingStrategy,
     /// A list of other schemas that are required from the registry to resolve this one
     ///
-    /// Any time calls to the schema registry are made to fetch schemas, these schema references
-    /// will be resolved first.
-    pub schema_references: Vec<SchemaDetails>,
+    /// These are sent along with the schema whenever it's registered, looked up or checked for
+    /// compatibility. When schemas are fetched, the references the registry holds for them are
+    /// resolved first.
+    pub schema_references: Vec<SchemaReference>,
     pub format: Format,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Format {
     Avro,
-    #[cfg(feature = "protobuf")]
+    #[cfg(feature = "proto")]
     Protobuf,
     #[cfg(feature = "json")]
     Json,
 }
 
+/// A reference from one schema to another which is registered under its own subject
+#[derive(Debug, Clone)]
+pub struct SchemaReference {
+    /// The name the referencing schema knows the referenced schema by
+    ///
+    /// - For Avro, this is the fully qualified name of the referenced type
+    /// - For Protobuf, this is the path the referenced file is imported with
+    pub name: String,
+    /// Where to find the referenced schema, leave the version as `None` to reference the latest
+    pub details: SchemaDetails,
+}
+
+/// A schema reference as it is stored in the registry
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RegisteredReference {
+    pub name: String,
+    pub subject: String,
+    pub version: u32,
+}
+
+/// The raw schema behind a reference, once it has been fetched from the registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ResolvedReference {
+    pub(crate) name: String,
+    pub(crate) schema: String,
+}
+
 impl Format {
     pub fn parse_schema(&self, schema: &str) -> Result<Schema> {
+        self.parse_schema_with_references(schema, &[])
+    }
+
+    /// The references must be ordered so that every schema comes after the schemas it depends on
+    pub(crate) fn parse_schema_with_references(
+        &self,
+        schema: &str,
+        references: &[ResolvedReference],
+    ) -> Result<Schema> {
         match *self {
             #[cfg(feature = "avro")]
-            Self::Avro => Schema::new_avro_schema(schema),
-            _ => unimplemented!("Currently only Avro is supported"),
+            Self::Avro => Schema::new_avro_schema_with_references(schema, references),
+            #[cfg(not(feature = "avro"))]
+            Self::Avro => Err(Error::AvroNotEnabled),
+            #[cfg(feature = "proto")]
+            Self::Protobuf => Schema::new_protobuf_schema_with_references(schema, references),
+            #[cfg(feature = "json")]
+            Self::Json => Schema::new_json_schema_with_references(schema, references),
         }
     }
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Format {
     fn default() -> Self {
         Self::Avro
@@ -148,21 +196,66 @@ pub enum SubjectNamingStrategy {
 
 #[derive(Debug, PartialEq)]
 pub enum Schema {
-    #[cfg(feature = "protobuf")]
-    Protobuf(i32),
+    #[cfg(feature = "proto")]
+    Protobuf(Arc<ProtobufSchema>),
+    #[cfg(feature = "json")]
+    Json(Arc<JsonSchema>),
+    #[cfg(feature = "avro")]
     Avro(Arc<AvroSchema>),
 }
 
 impl Schema {
+    #[cfg(feature = "avro")]
     pub fn new_avro_schema(schema: &str) -> Result<Self> {
         let sch = AvroSchema::parse_str(schema)?;
         Ok(Self::Avro(Arc::new(sch)))
     }
 
+    /// Avro can only resolve named types across schemas when they are all parsed together, so the
+    /// referenced schemas are parsed alongside this one and then discarded
+    #[cfg(feature = "avro")]
+    pub(crate) fn new_avro_schema_with_references(
+        schema: &str,
+        references: &[ResolvedReference],
+    ) -> Result<Self> {
+        if references.is_empty() {
+            return Self::new_avro_schema(schema);
+        }
+        let mut raw_schemas: Vec<&str> = references.iter().map(|r| r.schema.as_str()).collect();
+        raw_schemas.push(schema);
+        let sch = AvroSchema::parse_list(&raw_schemas)?
+            .pop()
+            .ok_or(Error::UnexpectedError)?;
+        Ok(Self::Avro(Arc::new(sch)))
+    }
+
+    /// Imports are resolved against the references by name
+    #[cfg(feature = "proto")]
+    pub(crate) fn new_protobuf_schema_with_references(
+        schema: &str,
+        references: &[ResolvedReference],
+    ) -> Result<Self> {
+        let sch = ProtobufSchema::parse(schema, references)?;
+        Ok(Self::Protobuf(Arc::new(sch)))
+    }
+
+    /// References are made available to `$ref` by name
+    #[cfg(feature = "json")]
+    pub(crate) fn new_json_schema_with_references(
+        schema: &str,
+        references: &[ResolvedReference],
+    ) -> Result<Self> {
+        let sch = JsonSchema::parse(schema, references)?;
+        Ok(Self::Json(Arc::new(sch)))
+    }
+
     pub(crate) fn schema_type(&self) -> &str {
         match *self {
-            #[cfg(feature = "protobuf")]
+            #[cfg(feature = "proto")]
             Self::Protobuf(_) => "Protobuf",
+            #[cfg(feature = "json")]
+            Self::Json(_) => "Json",
+            #[cfg(feature = "avro")]
             Self::Avro(_) => "Avro",
         }
     }
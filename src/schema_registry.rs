@@ -1,16 +1,32 @@
 use dashmap::DashMap;
-use futures_locks::RwLock;
+use futures_util::{future, stream, StreamExt, TryStreamExt};
 use lazy_static::lazy_static;
 use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE};
-use reqwest::Client;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::deserializer::{CachedDeserializer, Deserializer};
-use crate::schema::{Format, Schema, SchemaDetails};
-use crate::serializer::Serializer;
-use crate::{Error, Result};
+use crate::auth::Authentication;
+use crate::builder::SchemaRegistryBuilder;
+use crate::cache::{CacheSnapshot, CacheStats, CachedVersion, RawSchema, SchemaCache};
+use crate::compatibility::{
+    CompatibilityCheck, CompatibilityLevel, CompatibilityRequest, CompatibilityResponse,
+};
+use crate::deserializer::{CachedDeserializer, Deserializer, DeserializerConfig, LocalSchemas};
+use crate::disk_cache::DiskCache;
+use crate::mode::{ModeBody, RegistryMode};
+use crate::nodes::Nodes;
+use crate::retry::RetryPolicy;
+use crate::schema::{Format, RegisteredReference, ResolvedReference, Schema, SchemaDetails};
+use crate::serializer::{Serializer, SerializerMode};
+use crate::single_flight::SingleFlight;
+use crate::{Error, RegistryErrorCode, Result};
 
 lazy_static! {
     static ref HEADERS: HeaderMap = {
@@ -27,58 +43,243 @@ lazy_static! {
     };
 }
 
-#[derive(Default)]
+/// How much of an unexpected response body is kept in the error
+const MAX_ERROR_BODY_CHARS: usize = 512;
+
 pub struct SchemaRegistry {
-    schemas: DashMap<u32, Arc<Schema>>,
-    subject_to_latest_id: DashMap<String, u32>,
+    schemas: SchemaCache,
+    subject_to_latest_id: DashMap<String, (u32, Instant)>,
+    latest_ttl: Option<Duration>,
+    prefetch_concurrency: usize,
+    disk_cache: Option<DiskCache>,
     subject_version_to_id: DashMap<(String, u32), u32>,
+    in_flight: SingleFlight<FetchKey, SchemaRef>,
     http_client: Client,
-    url: String,
+    auth: Option<Authentication>,
+    nodes: Nodes,
+    retry_policy: RetryPolicy,
+}
+
+/// A registry without any URLs, which can only use the schemas it is given through
+/// [`import_cache`](SchemaRegistry::import_cache), every request fails with
+/// [`Error::InvalidConfig`]
+impl Default for SchemaRegistry {
+    fn default() -> Self {
+        Self::from_builder(SchemaRegistryBuilder::new(""), Client::new())
+    }
 }
 
 impl SchemaRegistry {
+    /// # Panics
+    ///
+    /// If `registry_url` doesn't contain any URLs, use [`builder`](Self::builder) to get an error
+    /// instead
     pub fn new(registry_url: String) -> Self {
-        Self::new_with_client(Default::default(), registry_url)
+        Self::builder(registry_url)
+            .build()
+            .expect("the registry URL should not be empty")
     }
 
+    /// # Panics
+    ///
+    /// If `registry_url` doesn't contain any URLs, use [`builder`](Self::builder) to get an error
+    /// instead
     pub fn new_with_client(client: Client, registry_url: String) -> Self {
+        Self::builder(registry_url)
+            .client(client)
+            .build()
+            .expect("the registry URL should not be empty")
+    }
+
+    /// Start configuring a registry, for when the defaults used by [`new`](Self::new) don't fit
+    pub fn builder(registry_url: impl Into<String>) -> SchemaRegistryBuilder {
+        SchemaRegistryBuilder::new(registry_url)
+    }
+
+    pub(crate) fn from_builder(builder: SchemaRegistryBuilder, http_client: Client) -> Self {
+        let schemas = SchemaCache::new(builder.max_cached_schemas, builder.max_cache_size);
+        let disk_cache = builder.disk_cache.map(DiskCache::new);
+        if let Some(disk_cache) = &disk_cache {
+            for (id, raw) in disk_cache.load() {
+                if let Ok(schema) = raw.parse() {
+                    schemas.insert(id, Arc::new(schema), Arc::new(raw));
+                }
+            }
+        }
         Self {
-            schemas: Default::default(),
+            schemas,
+            disk_cache,
             subject_to_latest_id: Default::default(),
+            latest_ttl: builder.latest_ttl,
+            prefetch_concurrency: builder.prefetch_concurrency,
             subject_version_to_id: Default::default(),
-            http_client: client,
-            url: registry_url,
+            in_flight: Default::default(),
+            http_client,
+            auth: builder.auth,
+            nodes: Nodes::new(builder.urls, builder.failover, builder.unhealthy_cooldown),
+            retry_policy: builder.retry_policy,
+        }
+    }
+
+    /// How the cache of parsed schemas has been used since the registry was created
+    pub fn cache_stats(&self) -> CacheStats {
+        self.schemas.stats()
+    }
+
+    /// Takes a copy of everything in the cache, which can be serialized and later loaded into
+    /// another registry with [`import_cache`](Self::import_cache)
+    ///
+    /// This includes the raw schema behind every cached id, as well as which ids the cached
+    /// subjects and versions point to
+    pub fn export_cache(&self) -> CacheSnapshot {
+        CacheSnapshot {
+            schemas: self
+                .schemas
+                .raw_schemas()
+                .into_iter()
+                .map(|(id, raw)| (id, RawSchema::clone(&raw)))
+                .collect(),
+            latest: self
+                .subject_to_latest_id
+                .iter()
+                .map(|e| (e.key().clone(), e.value().0))
+                .collect(),
+            versions: self
+                .subject_version_to_id
+                .iter()
+                .map(|e| {
+                    let (subject, version) = e.key();
+                    CachedVersion {
+                        subject: subject.clone(),
+                        version: *version,
+                        id: *e.value(),
+                    }
+                })
+                .collect(),
         }
     }
 
+    /// Loads a snapshot taken with [`export_cache`](Self::export_cache), without going to the
+    /// registry
+    ///
+    /// Every schema in the snapshot is parsed before anything is added to the cache, so if any of
+    /// them fail to parse the cache is left untouched. The latest version of each subject is
+    /// treated as if it had just been fetched.
+    pub fn import_cache(&self, snapshot: CacheSnapshot) -> Result<()> {
+        let schemas = snapshot
+            .schemas
+            .into_iter()
+            .map(|(id, raw)| Ok((id, Arc::new(raw.parse()?), Arc::new(raw))))
+            .collect::<Result<Vec<_>>>()?;
+        for (id, schema, raw) in schemas {
+            self.schemas.insert(id, schema, raw);
+        }
+        let now = Instant::now();
+        for (subject, id) in snapshot.latest {
+            self.subject_to_latest_id.insert(subject, (id, now));
+        }
+        for version in snapshot.versions {
+            self.subject_version_to_id
+                .insert((version.subject, version.version), version.id);
+        }
+        Ok(())
+    }
+
+    /// Forgets which schemas the subject's versions point to, so the next serializer or lookup for
+    /// the subject goes to the registry
+    ///
+    /// Schemas cached by id are kept, as an id always refers to the same schema
+    pub fn invalidate_subject(&self, subject: &str) {
+        self.subject_to_latest_id.remove(subject);
+        self.subject_version_to_id.retain(|(s, _), _| s != subject);
+    }
+
     /// Generate a serializer that is ready to serialize a type with the provided schema
     pub async fn get_serializer(&self, details: &SchemaDetails) -> Result<Serializer> {
         let schema = self.get_schema_by_subject(details).await?;
+        Self::serializer(schema, details)
+    }
+
+    /// Generate a serializer for the local raw schema, finding the schema it matches in the
+    /// registry according to `mode`
+    ///
+    /// This always asks the registry about the local schema, so the serializer should be kept and
+    /// reused rather than generated for every message.
+    pub async fn get_serializer_for(
+        &self,
+        schema: &str,
+        details: &SchemaDetails,
+        mode: SerializerMode,
+    ) -> Result<Serializer> {
+        let schema = match mode {
+            SerializerMode::AutoRegister => match self.lookup(schema, details).await {
+                Err(e) if is_unregistered(&e) => self.register(schema, details).await?,
+                result => result?,
+            },
+            SerializerMode::UseLatest => {
+                let check = self.check_compatibility(schema, details).await?;
+                if !check.is_compatible {
+                    return Err(Error::IncompatibleSchema(check.messages));
+                }
+                self.get_schema_by_subject(details).await?
+            }
+            SerializerMode::LookupOnly => self.lookup(schema, details).await?,
+        };
+        Self::serializer(schema, details)
+    }
+
+    #[allow(unreachable_patterns)]
+    fn serializer(schema: SchemaRef, details: &SchemaDetails) -> Result<Serializer> {
         match details.format {
+            #[cfg(feature = "avro")]
             Format::Avro => Ok(Serializer::Avro { schema }),
-            _ => unimplemented!("only avro is currently supported"),
+            #[cfg(not(feature = "avro"))]
+            Format::Avro => Err(Error::AvroNotEnabled),
+            #[cfg(feature = "proto")]
+            Format::Protobuf => {
+                let message = match &*schema.schema {
+                    Schema::Protobuf(s) => s.default_message(details)?,
+                    s => {
+                        return Err(Error::IncorrectSchemaType(
+                            "Protobuf".to_owned(),
+                            s.schema_type().to_string(),
+                        ))
+                    }
+                };
+                Ok(Serializer::Protobuf { schema, message })
+            }
+            #[cfg(feature = "json")]
+            Format::Json => Ok(Serializer::Json {
+                schema,
+                validate: false,
+            }),
         }
     }
 
     /// Generate a deserializer that is ready to deserialize any bytes which have previously been
     /// encoded with the Confluent Schema Registry protocol
     pub fn get_deserializer(&self) -> Deserializer<'_> {
-        Deserializer { registry: self }
+        Deserializer {
+            registry: self,
+            config: DeserializerConfig::default(),
+        }
     }
 
     /// Generate a deserializer that is ready to deserialize any bytes which have previously been
     /// encoded with the Confluent Schema Registry protocol
     ///
-    /// This deserializer will cache the schema of the first type it deserializes, and use that for
-    /// all subsequent deserilizations.
+    /// This deserializer keeps its own copy of every schema it has deserialized with, keyed by the
+    /// schema id in the payload, so after the first message for each id it never touches the
+    /// registry's shared cache. A payload with an id it hasn't seen yet is looked up in the
+    /// registry as normal.
     ///
-    /// This only really has the use case when you know you are only going to be deserializing one
-    /// type, and they all use the exact same version of the schema, however when this case is
-    /// true, this will allow you to reduce the network traffic for deserialization
+    /// This is most useful for hot consumers reading a small number of schemas, as it avoids any
+    /// contention on the registry's shared cache
     pub fn get_cached_deserializer(&self) -> CachedDeserializer<'_> {
         CachedDeserializer {
             registry: self,
-            schema: RwLock::new(None),
+            schemas: LocalSchemas::default(),
+            config: DeserializerConfig::default(),
         }
     }
 
@@ -95,40 +296,43 @@ impl SchemaRegistry {
             return Ok(resp);
         }
 
-        // @TODO - Add children schemas, they currently do nothing
-        // let mut child_schemas = Vec::with_capacity(schema_details.schema_references.len());
-        // for sub_schema in schema_details.schema_references {
-        //     let child_schema = self.get_schema(sub_schema).await?;
-        //     child_schemas.push(child_schema);
-        // }
+        // We need to request the schema (or the ID) from the registry, unless someone else is
+        // already doing so
+        self.in_flight
+            .run(FetchKey::Subject(subject.clone(), version), || {
+                self.fetch_schema_by_subject(&subject, version, schema_details.format)
+            })
+            .await
+    }
 
-        // We need to request the schema (or the ID) from the registry
+    async fn fetch_schema_by_subject(
+        &self,
+        subject: &str,
+        version: Option<u32>,
+        format: Format,
+    ) -> Result<SchemaRef> {
         match version {
             Some(version) => {
-                let (schema_id, schema) = self
-                    .fetch_schema(SchemaQueryType::Version(&subject, version))
+                let (schema_id, data) = self
+                    .fetch_schema(SchemaQueryType::Version(subject, version))
                     .await?;
-                let schema = schema_details.format.parse_schema(&schema)?;
+                let schema = self.parse_and_cache(schema_id, &data, format).await?;
                 self.subject_version_to_id
-                    .insert((subject.clone(), version), schema_id);
-                let resp = SchemaRef {
-                    schema: Arc::new(schema),
+                    .insert((subject.to_string(), version), schema_id);
+                Ok(SchemaRef {
+                    schema,
                     id: schema_id,
-                };
-                self.schemas.insert(schema_id, Arc::clone(&resp.schema));
-                Ok(resp)
+                })
             }
             None => {
-                let (schema_id, schema) =
-                    self.fetch_schema(SchemaQueryType::Latest(&subject)).await?;
-                let schema = schema_details.format.parse_schema(&schema)?;
-                self.subject_to_latest_id.insert(subject.clone(), schema_id);
-                let resp = SchemaRef {
-                    schema: Arc::new(schema),
+                let (schema_id, data) = self.fetch_schema(SchemaQueryType::Latest(subject)).await?;
+                let schema = self.parse_and_cache(schema_id, &data, format).await?;
+                self.subject_to_latest_id
+                    .insert(subject.to_string(), (schema_id, Instant::now()));
+                Ok(SchemaRef {
+                    schema,
                     id: schema_id,
-                };
-                self.schemas.insert(schema_id, Arc::clone(&resp.schema));
-                Ok(resp)
+                })
             }
         }
     }
@@ -138,14 +342,328 @@ impl SchemaRegistry {
             let resp = SchemaRef { schema, id };
             return Ok(resp);
         }
-        let (id, schema) = self.fetch_schema(SchemaQueryType::Id(id)).await?;
-        let schema = format.parse_schema(&schema)?;
-        let resp = SchemaRef {
-            schema: Arc::new(schema),
-            id,
+        self.in_flight
+            .run(FetchKey::Id(id), || {
+                self.fetch_schema_by_id(id, Some(format))
+            })
+            .await
+    }
+
+    /// Parses the schema as `format`, or as whatever type the registry says it is if that's `None`
+    async fn fetch_schema_by_id(&self, id: u32, format: Option<Format>) -> Result<SchemaRef> {
+        let (id, data) = self.fetch_schema(SchemaQueryType::Id(id)).await?;
+        let format = format.unwrap_or(data.schema_type);
+        let schema = self.parse_and_cache(id, &data, format).await?;
+        Ok(SchemaRef { schema, id })
+    }
+
+    /// Fetches and caches the schemas for all of the subjects up front, so the first serializer
+    /// for each of them doesn't have to wait on the registry
+    ///
+    /// Up to [`prefetch_concurrency`](SchemaRegistryBuilder::prefetch_concurrency) schemas are
+    /// fetched at once. This fails as soon as any of the schemas can't be fetched.
+    pub async fn prefetch(&self, details: &[SchemaDetails]) -> Result<()> {
+        stream::iter(details)
+            .map(|details| self.get_schema_by_subject(details))
+            .buffer_unordered(self.prefetch_concurrency)
+            .try_for_each(|_| future::ready(Ok(())))
+            .await
+    }
+
+    /// Fetches and caches the schemas with the given ids up front, so the first message
+    /// deserialized with each of them doesn't have to wait on the registry
+    ///
+    /// Each schema is parsed as whatever type the registry says it is. Up to
+    /// [`prefetch_concurrency`](SchemaRegistryBuilder::prefetch_concurrency) schemas are fetched
+    /// at once. This fails as soon as any of the schemas can't be fetched.
+    pub async fn prefetch_ids(&self, ids: &[u32]) -> Result<()> {
+        stream::iter(ids)
+            .map(|&id| async move {
+                if self.check_cache_for_schema(None, None, Some(id)).is_some() {
+                    return Ok(());
+                }
+                self.in_flight
+                    .run(FetchKey::Id(id), || self.fetch_schema_by_id(id, None))
+                    .await
+                    .map(|_| ())
+            })
+            .buffer_unordered(self.prefetch_concurrency)
+            .try_for_each(|_| future::ready(Ok(())))
+            .await
+    }
+
+    /// Lists all of the subjects currently registered with the schema registry
+    ///
+    /// - `prefix` restricts the results to subjects starting with the given value
+    /// - `include_deleted` will also return subjects that have been soft deleted
+    pub async fn list_subjects(
+        &self,
+        prefix: Option<&str>,
+        include_deleted: bool,
+    ) -> Result<Vec<String>> {
+        let path = "/subjects".to_owned();
+        let mut query = Vec::with_capacity(2);
+        if let Some(prefix) = prefix {
+            query.push(("subjectPrefix", prefix));
+        }
+        if include_deleted {
+            query.push(("deleted", "true"));
+        }
+        let mut subjects = self.get::<Vec<String>>(&path, &query).await?;
+        // Older versions of the registry ignore the prefix, so make sure it's applied
+        if let Some(prefix) = prefix {
+            subjects.retain(|subject| subject.starts_with(prefix));
+        }
+        Ok(subjects)
+    }
+
+    /// Lists all of the versions registered under the provided subject
+    ///
+    /// `include_deleted` will also return versions that have been soft deleted
+    pub async fn list_versions(&self, subject: &str, include_deleted: bool) -> Result<Vec<u32>> {
+        let path = format!("/subjects/{}/versions", subject);
+        let query: &[(&str, &str)] = if include_deleted {
+            &[("deleted", "true")]
+        } else {
+            &[]
+        };
+        self.get::<Vec<u32>>(&path, query).await
+    }
+
+    /// Fetches the full registry metadata for a version of a subject, leave the version as `None`
+    /// to fetch the latest
+    ///
+    /// This always calls out to the registry and has no effect on the cache
+    pub async fn get_subject_version_metadata(
+        &self,
+        subject: &str,
+        version: Option<u32>,
+    ) -> Result<SubjectVersion> {
+        let path = match version {
+            Some(version) => format!("/subjects/{}/versions/{}", subject, version),
+            None => format!("/subjects/{}/versions/latest", subject),
+        };
+        self.get::<SubjectVersion>(&path, &[]).await
+    }
+
+    /// Deletes every version of the subject from the registry, returning the versions that were
+    /// deleted
+    ///
+    /// A `permanent` (hard) delete can only be made once the subject has already been soft
+    /// deleted. Either way, any schemas cached against this subject are evicted.
+    pub async fn delete_subject(&self, subject: &str, permanent: bool) -> Result<Vec<u32>> {
+        let path = format!("/subjects/{}", subject);
+        let versions = self
+            .delete::<Vec<u32>>(&path, permanent_query(permanent))
+            .await?;
+        self.evict_subject(subject, None);
+        Ok(versions)
+    }
+
+    /// Deletes a single version of the subject from the registry, returning the version that was
+    /// deleted
+    ///
+    /// A `permanent` (hard) delete can only be made once the version has already been soft
+    /// deleted. Either way, any schemas cached against this version are evicted.
+    pub async fn delete_subject_version(
+        &self,
+        subject: &str,
+        version: u32,
+        permanent: bool,
+    ) -> Result<u32> {
+        let path = format!("/subjects/{}/versions/{}", subject, version);
+        let version = self
+            .delete::<u32>(&path, permanent_query(permanent))
+            .await?;
+        self.evict_subject(subject, Some(version));
+        Ok(version)
+    }
+
+    /// Asks the registry whether the raw schema is compatible with the version of the subject
+    /// described by the schema details, without registering anything
+    ///
+    /// If no version is provided then the check is made against the latest version. The result
+    /// includes the registry's reasons for any incompatibility.
+    pub async fn check_compatibility(
+        &self,
+        schema: &str,
+        details: &SchemaDetails,
+    ) -> Result<CompatibilityCheck> {
+        let version = details
+            .version
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "latest".to_owned());
+        let path = format!(
+            "/compatibility/subjects/{}/versions/{}",
+            details.generate_subject_name(),
+            version
+        );
+        let references = self.registered_references(details).await?;
+        let req = SchemaRegistryRequest::new(schema, details.format, references);
+        self.post_schema::<CompatibilityCheck>(&path, &[("verbose", "true")], &req)
+            .await
+    }
+
+    /// Fetches the compatibility level the registry applies to subjects without their own
+    pub async fn get_global_compatibility(&self) -> Result<CompatibilityLevel> {
+        let path = "/config".to_owned();
+        let resp = self.get::<CompatibilityResponse>(&path, &[]).await?;
+        Ok(resp.compatibility)
+    }
+
+    /// Updates the compatibility level the registry applies to subjects without their own
+    pub async fn set_global_compatibility(
+        &self,
+        level: CompatibilityLevel,
+    ) -> Result<CompatibilityLevel> {
+        let path = "/config".to_owned();
+        let req = CompatibilityRequest {
+            compatibility: level,
+        };
+        let resp = self
+            .put::<_, CompatibilityResponse>(&path, &[], &req)
+            .await?;
+        Ok(resp.compatibility)
+    }
+
+    /// Fetches the compatibility level configured for the subject
+    ///
+    /// If the subject doesn't have its own level the registry will return an error, unless
+    /// `default_to_global` is set, in which case the global level is returned instead
+    pub async fn get_subject_compatibility(
+        &self,
+        subject: &str,
+        default_to_global: bool,
+    ) -> Result<CompatibilityLevel> {
+        let path = format!("/config/{}", subject);
+        let query: &[(&str, &str)] = if default_to_global {
+            &[("defaultToGlobal", "true")]
+        } else {
+            &[]
+        };
+        let resp = self.get::<CompatibilityResponse>(&path, query).await?;
+        Ok(resp.compatibility)
+    }
+
+    /// Updates the compatibility level for the subject, overriding the global level
+    pub async fn set_subject_compatibility(
+        &self,
+        subject: &str,
+        level: CompatibilityLevel,
+    ) -> Result<CompatibilityLevel> {
+        let path = format!("/config/{}", subject);
+        let req = CompatibilityRequest {
+            compatibility: level,
+        };
+        let resp = self
+            .put::<_, CompatibilityResponse>(&path, &[], &req)
+            .await?;
+        Ok(resp.compatibility)
+    }
+
+    /// Removes the subject's own compatibility level, so that it falls back to the global level
+    ///
+    /// Returns the level the subject had before it was removed
+    pub async fn delete_subject_compatibility(&self, subject: &str) -> Result<CompatibilityLevel> {
+        let path = format!("/config/{}", subject);
+        let resp = self.delete::<CompatibilityResponse>(&path, &[]).await?;
+        Ok(resp.compatibility)
+    }
+
+    /// Fetches the global mode of the registry
+    pub async fn get_mode(&self) -> Result<RegistryMode> {
+        let path = "/mode".to_owned();
+        let resp = self.get::<ModeBody>(&path, &[]).await?;
+        Ok(resp.mode)
+    }
+
+    /// Updates the global mode of the registry
+    ///
+    /// `force` is required to move a registry which already contains schemas into
+    /// [`RegistryMode::Import`]
+    pub async fn set_mode(&self, mode: RegistryMode, force: bool) -> Result<RegistryMode> {
+        let path = "/mode".to_owned();
+        let resp = self
+            .put::<_, ModeBody>(&path, force_query(force), &ModeBody { mode })
+            .await?;
+        Ok(resp.mode)
+    }
+
+    /// Fetches the mode of the subject, the registry will fall back to the global mode if the
+    /// subject doesn't have its own
+    pub async fn get_subject_mode(&self, subject: &str) -> Result<RegistryMode> {
+        let path = format!("/mode/{}", subject);
+        let resp = self.get::<ModeBody>(&path, &[]).await?;
+        Ok(resp.mode)
+    }
+
+    /// Updates the mode of the subject, overriding the global mode
+    ///
+    /// `force` is required to move a subject which already contains schemas into
+    /// [`RegistryMode::Import`]
+    pub async fn set_subject_mode(
+        &self,
+        subject: &str,
+        mode: RegistryMode,
+        force: bool,
+    ) -> Result<RegistryMode> {
+        let path = format!("/mode/{}", subject);
+        let resp = self
+            .put::<_, ModeBody>(&path, force_query(force), &ModeBody { mode })
+            .await?;
+        Ok(resp.mode)
+    }
+
+    /// Registers the raw schema under the subject with an explicit ID and version, returning the
+    /// ID the registry stored it under
+    ///
+    /// The registry (or the subject) has to be in [`RegistryMode::Import`] for this to succeed.
+    /// This is intended for migrating schemas between registries, where the IDs already embedded
+    /// in produced messages have to be preserved.
+    pub async fn import_schema(
+        &self,
+        schema: &str,
+        details: &SchemaDetails,
+        id: u32,
+        version: u32,
+    ) -> Result<u32> {
+        let subject = details.generate_subject_name();
+        let path = format!("/subjects/{}/versions", subject);
+        let references = self.registered_references(details).await?;
+        let req = SchemaRegistryRequest {
+            id: Some(id),
+            version: Some(version),
+            ..SchemaRegistryRequest::new(schema, details.format, references)
         };
-        self.schemas.insert(id, Arc::clone(&resp.schema));
-        Ok(resp)
+        let resp = self
+            .post_schema::<SchemaRegistryPostResponse>(&path, &[], &req)
+            .await?;
+        let raw = RawSchema {
+            format: details.format,
+            schema: schema.to_string(),
+            references: self.resolve_references(&req.references).await?,
+        };
+        self.cache_schema(resp.id, raw).await?;
+        self.subject_version_to_id
+            .insert((subject, version), resp.id);
+        Ok(resp.id)
+    }
+
+    /// Checks whether the raw schema is already registered under the subject, without ever
+    /// registering it
+    ///
+    /// Returns `None` if either the subject or the schema can't be found in the registry, if it is
+    /// found then it will also be cached.
+    pub async fn lookup_schema(
+        &self,
+        schema: &str,
+        details: &SchemaDetails,
+    ) -> Result<Option<SchemaRef>> {
+        match self.lookup(schema, details).await {
+            Ok(schema) => Ok(Some(schema)),
+            Err(e) if is_unregistered(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
     /// Takes a reference to a slice of raw schema strings and their corresponding schema details
@@ -153,38 +671,55 @@ impl SchemaRegistry {
     /// identification details of all of those schemas
     pub async fn post_schemas_to_registry(&self, schemas: &[(&str, &SchemaDetails)]) -> Result<()> {
         for (schema, details) in schemas {
-            let url = format!(
-                "{}/subjects/{}/versions",
-                self.url,
-                details.generate_subject_name()
-            );
-            let req = SchemaRegistryRequest {
-                schema,
-                schema_type: details.format,
-            };
+            let path = format!("/subjects/{}/versions", details.generate_subject_name());
+            let references = self.registered_references(details).await?;
+            let req = SchemaRegistryRequest::new(schema, details.format, references);
             // I don't really like this, but this call is required to add a NEW schema
             // however it doesn't return a full set of information, so we basically ignore it
-            match self
-                .post_schema::<SchemaRegistryPostResponse>(&url, &req)
-                .await
-            {
-                Ok(_) => {}
-                Err(e) => return Err(e),
-            };
-            let url = format!("{}/subjects/{}", self.url, details.generate_subject_name());
+            self.post_schema::<SchemaRegistryPostResponse>(&path, &[], &req)
+                .await?;
+            // The schema may already have been an older version, so whether it's the latest can't
+            // be known from here, and a new version makes any cached latest version stale
+            self.subject_to_latest_id
+                .remove(&details.generate_subject_name());
+            let path = format!("/subjects/{}", details.generate_subject_name());
             // This call actually gives us the information we need, however it won't add a schema
             // if it doesn't already exist
             let schema = self
-                .post_schema::<SchemaRegistryResponse>(&url, &req)
-                .await
-                .map(parse_post_response)??;
-            self.parse_response(schema, details.format, details.version)?;
+                .post_schema::<SchemaRegistryData>(&path, &[], &req)
+                .await?;
+            self.parse_response(schema, details.format, false).await?;
         }
         Ok(())
     }
 }
 
 impl SchemaRegistry {
+    /// Finds the raw schema among the versions of the subject, failing with the registry's error
+    /// if either of them can't be found
+    async fn lookup(&self, schema: &str, details: &SchemaDetails) -> Result<SchemaRef> {
+        let path = format!("/subjects/{}", details.generate_subject_name());
+        let references = self.registered_references(details).await?;
+        let req = SchemaRegistryRequest::new(schema, details.format, references);
+        let response = self
+            .post_schema::<SchemaRegistryData>(&path, &[], &req)
+            .await?;
+        self.parse_response(response, details.format, false).await
+    }
+
+    /// Registers the raw schema as a new version of the subject, then looks it up to find out
+    /// which version it became
+    async fn register(&self, schema: &str, details: &SchemaDetails) -> Result<SchemaRef> {
+        let subject = details.generate_subject_name();
+        let path = format!("/subjects/{}/versions", subject);
+        let references = self.registered_references(details).await?;
+        let req = SchemaRegistryRequest::new(schema, details.format, references);
+        self.post_schema::<SchemaRegistryPostResponse>(&path, &[], &req)
+            .await?;
+        self.subject_to_latest_id.remove(&subject);
+        self.lookup(schema, details).await
+    }
+
     /// Checks whether the cache currently contains the schema that's been asked for.
     ///
     /// It always priorities checking the ID first
@@ -195,8 +730,7 @@ impl SchemaRegistry {
         id: Option<u32>,
     ) -> Option<(u32, Arc<Schema>)> {
         if let Some(id) = id {
-            let s = self.schemas.get(&id)?;
-            return Some((id, Arc::clone(s.value())));
+            return Some((id, self.schemas.get(id)?));
         }
         if let Some(subject) = subject {
             let id = if let Some(version) = version {
@@ -204,106 +738,388 @@ impl SchemaRegistry {
                     .get(&(subject.to_string(), version))
                     .map(|v| *v.value())
             } else {
-                self.subject_to_latest_id.get(subject).map(|v| *v.value())
+                let (id, fetched_at) = *self.subject_to_latest_id.get(subject)?.value();
+                match self.latest_ttl {
+                    Some(ttl) if fetched_at.elapsed() >= ttl => None,
+                    _ => Some(id),
+                }
             }?;
-            let s = self.schemas.get(&id)?;
-            return Some((id, Arc::clone(s.value())));
+            return Some((id, self.schemas.get(id)?));
         }
         None
     }
 
-    /// Returns (Schema ID, Raw Schema)
-    async fn fetch_schema(&self, query: SchemaQueryType<'_>) -> Result<(u32, String)> {
+    /// Returns (Schema ID, Schema Data)
+    async fn fetch_schema(&self, query: SchemaQueryType<'_>) -> Result<(u32, SchemaRegistryData)> {
         match query {
             SchemaQueryType::Id(id) => {
-                let url = format!("{}/schemas/ids/{}", self.url, id);
-                let (_, schema) = self.get_schema(&url).await?;
-                Ok((id, schema))
+                let path = format!("/schemas/ids/{}", id);
+                let data = self.get_schema(&path).await?;
+                Ok((id, data))
             }
             SchemaQueryType::Latest(subject) => {
-                let url = format!("{}/subjects/{}/versions/latest", self.url, subject);
-                let (id, schema) = self.get_schema(&url).await?;
-                if id.is_none() {
-                    return Err(Error::IDNotReturned);
-                }
-                Ok((id.unwrap(), schema))
+                let path = format!("/subjects/{}/versions/latest", subject);
+                let data = self.get_schema(&path).await?;
+                let id = data.id.ok_or(Error::IDNotReturned)?;
+                Ok((id, data))
             }
             SchemaQueryType::Version(subject, version) => {
-                let url = format!("{}/subjects/{}/versions/{}", self.url, subject, version);
-                let (id, schema) = self.get_schema(&url).await?;
-                if id.is_none() {
-                    return Err(Error::IDNotReturned);
+                let path = format!("/subjects/{}/versions/{}", subject, version);
+                let data = self.get_schema(&path).await?;
+                let id = data.id.ok_or(Error::IDNotReturned)?;
+                Ok((id, data))
+            }
+        }
+    }
+
+    async fn get_schema(&self, path: &str) -> Result<SchemaRegistryData> {
+        self.get(path, &[]).await
+    }
+
+    /// Resolves everything the fetched schema references, parses them all together, and caches
+    /// the result under the schema's id
+    async fn parse_and_cache(
+        &self,
+        id: u32,
+        data: &SchemaRegistryData,
+        format: Format,
+    ) -> Result<Arc<Schema>> {
+        let raw = RawSchema {
+            format,
+            schema: data.schema.clone(),
+            references: self.resolve_references(&data.references).await?,
+        };
+        self.cache_schema(id, raw).await
+    }
+
+    /// Parses the schema and caches it in memory, and on disk if there is a disk cache
+    async fn cache_schema(&self, id: u32, raw: RawSchema) -> Result<Arc<Schema>> {
+        let schema = Arc::new(raw.parse()?);
+        let raw = Arc::new(raw);
+        if let Some(disk_cache) = &self.disk_cache {
+            disk_cache.store(id, Arc::clone(&raw)).await;
+        }
+        self.schemas.insert(id, Arc::clone(&schema), raw);
+        Ok(schema)
+    }
+
+    /// Fetches the raw schemas behind the references, along with everything they reference in
+    /// turn
+    ///
+    /// The result is ordered so that every schema comes after the schemas it depends on, and any
+    /// schema that is referenced more than once only appears the first time it's seen
+    async fn resolve_references(
+        &self,
+        references: &[RegisteredReference],
+    ) -> Result<Vec<ResolvedReference>> {
+        let mut seen = HashSet::new();
+        let mut resolved = Vec::new();
+        self.resolve_references_into(references, &mut seen, &mut resolved)
+            .await?;
+        Ok(resolved)
+    }
+
+    fn resolve_references_into<'a>(
+        &'a self,
+        references: &'a [RegisteredReference],
+        seen: &'a mut HashSet<(String, u32)>,
+        resolved: &'a mut Vec<ResolvedReference>,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            for reference in references {
+                if !seen.insert((reference.subject.clone(), reference.version)) {
+                    continue;
                 }
-                Ok((id.unwrap(), schema))
+                let (_, data) = self
+                    .fetch_schema(SchemaQueryType::Version(
+                        &reference.subject,
+                        reference.version,
+                    ))
+                    .await?;
+                self.resolve_references_into(&data.references, seen, resolved)
+                    .await?;
+                resolved.push(ResolvedReference {
+                    name: reference.name.clone(),
+                    schema: data.schema,
+                });
             }
+            Ok(())
+        })
+    }
+
+    /// Converts the references in the schema details into the form the registry expects, any
+    /// reference without a version is pinned to the latest version of its subject
+    async fn registered_references(
+        &self,
+        details: &SchemaDetails,
+    ) -> Result<Vec<RegisteredReference>> {
+        let mut references = Vec::with_capacity(details.schema_references.len());
+        for reference in &details.schema_references {
+            let subject = reference.details.generate_subject_name();
+            let version = match reference.details.version {
+                Some(version) => version,
+                None => {
+                    self.get_subject_version_metadata(&subject, None)
+                        .await?
+                        .version
+                }
+            };
+            references.push(RegisteredReference {
+                name: reference.name.clone(),
+                subject,
+                version,
+            });
         }
+        Ok(references)
     }
 
-    async fn get_schema(&self, url: &str) -> Result<(Option<u32>, String)> {
-        let response = self
-            .http_client
-            .get(url)
-            .headers(HEADERS.clone())
-            .send()
-            .await?
-            .json::<SchemaRegistryResponse>()
+    async fn get<D: DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> Result<D> {
+        self.send(path, |url| self.http_client.get(url).query(query))
+            .await
+    }
+
+    async fn delete<D: DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> Result<D> {
+        self.send(path, |url| self.http_client.delete(url).query(query))
+            .await
+    }
+
+    async fn put<R: Serialize, D: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        req: &R,
+    ) -> Result<D> {
+        self.send(path, |url| self.http_client.put(url).query(query).json(req))
             .await
-            .map(|resp| parse_post_response(resp).map(|data| (data.id, data.schema)))??;
-        Ok(response)
     }
 
     async fn post_schema<D: DeserializeOwned>(
         &self,
-        url: &str,
+        path: &str,
+        query: &[(&str, &str)],
         req: &SchemaRegistryRequest<'_>,
     ) -> Result<D> {
-        let response = self
-            .http_client
-            .post(url)
-            .headers(HEADERS.clone())
-            .json(req)
-            .send()
-            .await?
-            .json::<D>()
-            .await?;
-        Ok(response)
+        self.send(path, |url| {
+            self.http_client.post(url).query(query).json(req)
+        })
+        .await
     }
 
-    fn parse_response(
+    async fn send<D: DeserializeOwned>(
         &self,
-        response: SchemaRegistryData,
-        format: Format,
-        version: Option<u32>,
-    ) -> Result<()> {
-        let parsed_schema = format.parse_schema(&response.schema)?;
-        if let Some(id) = response.id {
-            self.schemas.insert(id, Arc::new(parsed_schema));
-            if let Some(subject) = response.subject {
-                if version.is_none() {
-                    self.subject_to_latest_id.insert(subject.clone(), id);
+        path: &str,
+        request: impl Fn(String) -> RequestBuilder,
+    ) -> Result<D> {
+        let response = self.execute(path, request).await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        parse_body(status, path, &body)
+    }
+
+    /// Sends the request, retrying it according to the retry policy
+    ///
+    /// `request` is given the full URL of the registry node being tried
+    async fn execute(
+        &self,
+        path: &str,
+        request: impl Fn(String) -> RequestBuilder,
+    ) -> Result<Response> {
+        let policy = &self.retry_policy;
+        let mut attempt = 1;
+        loop {
+            let result = self.execute_once(path, &request).await;
+            let should_retry = match &result {
+                Ok(response) => policy.should_retry_response(response),
+                Err(Error::Http(e)) => policy.should_retry_error(e),
+                Err(_) => false,
+            };
+            if !should_retry || attempt >= policy.max_attempts {
+                return result.map_err(|e| match e {
+                    Error::Http(e) if e.is_connect() || e.is_timeout() => {
+                        Error::RegistryUnreachable {
+                            path: path.to_owned(),
+                            source: e,
+                        }
+                    }
+                    e => e,
+                });
+            }
+            let delay = policy.delay(attempt, result.as_ref().ok());
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Sends the request to the first registry node that responds, with the headers and
+    /// authentication every registry request needs
+    ///
+    /// A node is marked as unhealthy and the next one is tried if it can't be reached, or if it
+    /// responds with a server error. If every node fails, the last node's response or error is
+    /// returned.
+    async fn execute_once(
+        &self,
+        path: &str,
+        request: &impl Fn(String) -> RequestBuilder,
+    ) -> Result<Response> {
+        let order = self.nodes.order();
+        let mut last = None;
+        for (attempt, &node) in order.iter().enumerate() {
+            let mut builder =
+                request(format!("{}{}", self.nodes.url(node), path)).headers(HEADERS.clone());
+            if let Some(auth) = &self.auth {
+                builder = auth.apply(builder).await?;
+            }
+            let is_last = attempt + 1 == order.len();
+            match builder.send().await {
+                Ok(response) if response.status().is_server_error() && !is_last => {
+                    self.nodes.mark_unhealthy(node);
                 }
-                if let Some(version) = response.version {
-                    self.subject_version_to_id.insert((subject, version), id);
+                Ok(response) => {
+                    if response.status().is_server_error() {
+                        self.nodes.mark_unhealthy(node);
+                    } else {
+                        self.nodes.mark_healthy(node);
+                    }
+                    return Ok(response);
                 }
+                Err(e) if e.is_connect() || e.is_timeout() => {
+                    self.nodes.mark_unhealthy(node);
+                    last = Some(e);
+                }
+                Err(e) => return Err(e.into()),
             }
         }
-        Ok(())
+        match last {
+            Some(e) => Err(e.into()),
+            None => Err(Error::InvalidConfig(
+                "The registry doesn't have any URLs to send requests to".to_owned(),
+            )),
+        }
+    }
+
+    /// Removes everything cached against the subject, or only against one version of it
+    ///
+    /// The latest version is always forgotten, as deleting any version may have changed it
+    fn evict_subject(&self, subject: &str, version: Option<u32>) {
+        let mut evicted_ids = Vec::new();
+        let latest = self
+            .subject_to_latest_id
+            .remove(subject)
+            .map(|(subject, (id, _))| (subject, id));
+        match version {
+            Some(version) => {
+                if let Some((_, id)) = self
+                    .subject_version_to_id
+                    .remove(&(subject.to_string(), version))
+                {
+                    evicted_ids.push(id);
+                }
+            }
+            None => {
+                evicted_ids.extend(latest.map(|(_, id)| id));
+                self.subject_version_to_id.retain(|(s, _), id| {
+                    if s == subject {
+                        evicted_ids.push(*id);
+                        return false;
+                    }
+                    true
+                });
+            }
+        }
+        for id in evicted_ids {
+            self.schemas.remove(id);
+        }
+    }
+
+    /// Parses and caches the schema, `is_latest` should only be set when the response is known to
+    /// be the latest version of the subject
+    async fn parse_response(
+        &self,
+        response: SchemaRegistryData,
+        format: Format,
+        is_latest: bool,
+    ) -> Result<SchemaRef> {
+        let id = response.id.ok_or(Error::IDNotReturned)?;
+        let schema = self.parse_and_cache(id, &response, format).await?;
+        if let Some(subject) = response.subject {
+            if is_latest {
+                self.subject_to_latest_id
+                    .insert(subject.clone(), (id, Instant::now()));
+            }
+            if let Some(version) = response.version {
+                self.subject_version_to_id.insert((subject, version), id);
+            }
+        }
+        Ok(SchemaRef { schema, id })
     }
 }
 
-fn parse_post_response(mut response: SchemaRegistryResponse) -> Result<SchemaRegistryData> {
-    if let Some(data) = response.data.take() {
-        return Ok(data);
+/// Whether the error means the subject, or the schema under it, hasn't been registered
+fn is_unregistered(error: &Error) -> bool {
+    matches!(
+        error.registry_error_code(),
+        Some(RegistryErrorCode::SubjectNotFound | RegistryErrorCode::SchemaNotFound)
+    )
+}
+
+fn permanent_query(permanent: bool) -> &'static [(&'static str, &'static str)] {
+    if permanent {
+        &[("permanent", "true")]
+    } else {
+        &[]
     }
-    if let Some(error) = response.error.take() {
-        return Err(Error::SchemaRegistryError {
-            error_code: error.error_code,
-            message: error
-                .message
-                .unwrap_or_else(|| "Unexpected error from the schema registry".to_owned()),
+}
+
+fn force_query(force: bool) -> &'static [(&'static str, &'static str)] {
+    if force {
+        &[("force", "true")]
+    } else {
+        &[]
+    }
+}
+
+/// Turns the registry's response into either the data that was asked for or the most specific error
+/// possible
+///
+/// Only successful responses are treated as data, anything else has to be one of the registry's
+/// errors. Whatever else comes back, such as an error page from a proxy or an empty body, is kept
+/// (cut short) in the error so that it's clear what went wrong.
+fn parse_body<D: DeserializeOwned>(status: StatusCode, path: &str, body: &[u8]) -> Result<D> {
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        let message = match serde_json::from_slice::<SchemaRegistryError>(body) {
+            Ok(SchemaRegistryError {
+                message: Some(message),
+                ..
+            }) => message,
+            _ => truncate_body(body),
+        };
+        return Err(Error::Unauthorized {
+            status,
+            path: path.to_owned(),
+            message,
         });
     }
-    Err(Error::UnexpectedError)
+    let parsed = if status.is_success() {
+        serde_json::from_slice::<RegistryResponse<D>>(body).map(|r| r.into_result(status, path))
+    } else {
+        serde_json::from_slice::<SchemaRegistryError>(body).map(|e| Err(e.into_error(status, path)))
+    };
+    parsed.unwrap_or_else(|_| {
+        Err(Error::UnexpectedResponse {
+            status,
+            path: path.to_owned(),
+            body: truncate_body(body),
+        })
+    })
+}
+
+fn truncate_body(body: &[u8]) -> String {
+    let body = String::from_utf8_lossy(body);
+    let body = body.trim();
+    match body.char_indices().nth(MAX_ERROR_BODY_CHARS) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body.to_owned(),
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -311,15 +1127,26 @@ fn parse_post_response(mut response: SchemaRegistryResponse) -> Result<SchemaReg
 struct SchemaRegistryRequest<'a> {
     schema: &'a str,
     schema_type: Format,
-    // references // @TODO
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    references: Vec<RegisteredReference>,
+    /// Only accepted by the registry while it's in import mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u32>,
+    /// Only accepted by the registry while it's in import mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<u32>,
 }
 
-#[derive(Debug, Deserialize)]
-struct SchemaRegistryResponse {
-    #[serde(flatten)]
-    data: Option<SchemaRegistryData>,
-    #[serde(flatten)]
-    error: Option<SchemaRegistryError>,
+impl<'a> SchemaRegistryRequest<'a> {
+    fn new(schema: &'a str, schema_type: Format, references: Vec<RegisteredReference>) -> Self {
+        Self {
+            schema,
+            schema_type,
+            references,
+            id: None,
+            version: None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -327,26 +1154,93 @@ struct SchemaRegistryPostResponse {
     id: u32,
 }
 
+/// Any response from the registry which isn't the standard schema payload
+///
+/// The registry errors are checked first, as they are always the same shape
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RegistryResponse<D> {
+    Error(SchemaRegistryError),
+    Data(D),
+}
+
+impl<D> RegistryResponse<D> {
+    fn into_result(self, status: StatusCode, path: &str) -> Result<D> {
+        match self {
+            Self::Data(data) => Ok(data),
+            Self::Error(error) => Err(error.into_error(status, path)),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct SchemaRegistryData {
     subject: Option<String>,
     id: Option<u32>,
     version: Option<u32>,
     schema: String,
+    #[serde(default, rename = "schemaType")]
+    schema_type: Format,
+    #[serde(default)]
+    references: Vec<RegisteredReference>,
 }
 
 #[derive(Debug, Deserialize)]
 struct SchemaRegistryError {
-    error_code: u32,
+    error_code: RegistryErrorCode,
     message: Option<String>,
 }
 
+impl SchemaRegistryError {
+    fn into_error(self, status: StatusCode, path: &str) -> Error {
+        Error::SchemaRegistryError {
+            status,
+            path: path.to_owned(),
+            error_code: self.error_code,
+            message: self
+                .message
+                .unwrap_or_else(|| "Unexpected error from the schema registry".to_owned()),
+        }
+    }
+}
+
+/// The registry's view of a single version of a schema registered under a subject
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct SubjectVersion {
+    pub subject: String,
+    /// The global ID of the schema
+    pub id: u32,
+    pub version: u32,
+    /// The registry omits this for Avro schemas, so it will default to Avro if it's missing
+    #[serde(default)]
+    pub schema_type: Format,
+    /// The raw schema, as it is stored in the registry
+    pub schema: String,
+    #[serde(default)]
+    pub references: Vec<RegisteredReference>,
+}
+
 #[derive(Debug, Clone)]
 pub struct SchemaRef {
     pub(crate) schema: Arc<Schema>,
     pub(crate) id: u32,
 }
 
+impl SchemaRef {
+    /// The global ID of the schema within the registry
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+/// Identifies a schema fetch, so concurrent fetches for the same schema can share one request
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum FetchKey {
+    Id(u32),
+    Subject(String, Option<u32>),
+}
+
 pub enum SchemaQueryType<'a> {
     /// Fetch the Schema by the global ID
     Id(u32),
@@ -355,3 +1249,129 @@ pub enum SchemaQueryType<'a> {
     /// Find the version of the schema with the given subject
     Version(&'a str, u32),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(status: u16, body: &str) -> Result<Vec<String>> {
+        parse_body(
+            StatusCode::from_u16(status).unwrap(),
+            "/subjects",
+            body.as_bytes(),
+        )
+    }
+
+    #[test]
+    fn it_parses_successful_responses() {
+        assert_eq!(parse(200, r#"["a", "b"]"#).unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn it_parses_registry_errors() {
+        let body = r#"{"error_code": 40401, "message": "Subject 'a' not found."}"#;
+        match parse(404, body) {
+            Err(Error::SchemaRegistryError {
+                status,
+                path,
+                error_code,
+                message,
+            }) => {
+                assert_eq!(status, 404);
+                assert_eq!(path, "/subjects");
+                assert_eq!(error_code, RegistryErrorCode::SubjectNotFound);
+                assert_eq!(message, "Subject 'a' not found.");
+            }
+            other => panic!("expected a registry error, got {:?}", other),
+        }
+        // Some proxies respond with a success status for the registry's errors
+        assert!(matches!(
+            parse(200, body),
+            Err(Error::SchemaRegistryError { status, .. }) if status == 200
+        ));
+    }
+
+    #[test]
+    fn it_treats_401_and_403_as_unauthorized() {
+        let html = "<html><body><h1>401 Authorization Required</h1></body></html>";
+        match parse(401, html) {
+            Err(Error::Unauthorized {
+                status,
+                path,
+                message,
+            }) => {
+                assert_eq!(status, 401);
+                assert_eq!(path, "/subjects");
+                assert_eq!(message, html);
+            }
+            other => panic!("expected an unauthorized error, got {:?}", other),
+        }
+        let json = r#"{"error_code": 40301, "message": "User cannot access the resource."}"#;
+        assert!(matches!(
+            parse(403, json),
+            Err(Error::Unauthorized { status, message, .. })
+                if status == 403 && message == "User cannot access the resource."
+        ));
+        assert!(matches!(
+            parse(401, ""),
+            Err(Error::Unauthorized { message, .. }) if message.is_empty()
+        ));
+    }
+
+    #[test]
+    fn it_keeps_unexpected_bodies() {
+        let cases = [
+            (404, ""),
+            (502, "<html><body>Bad Gateway</body></html>"),
+            (500, "Internal Server Error"),
+            (200, "<html><body>Login</body></html>"),
+            (200, r#"{"unexpected": true}"#),
+        ];
+        for (code, body) in cases {
+            match parse(code, body) {
+                Err(Error::UnexpectedResponse {
+                    status,
+                    path,
+                    body: kept,
+                }) => {
+                    assert_eq!(status, code);
+                    assert_eq!(path, "/subjects");
+                    assert_eq!(kept, body);
+                }
+                other => panic!("expected an unexpected response, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn it_needs_a_schema_in_schema_responses() {
+        let body = r#"{"status":"ok"}"#;
+        let result =
+            parse_body::<SchemaRegistryData>(StatusCode::OK, "/schemas/ids/42", body.as_bytes());
+        match result {
+            Err(Error::UnexpectedResponse {
+                status,
+                path,
+                body: kept,
+            }) => {
+                assert_eq!(status, 200);
+                assert_eq!(path, "/schemas/ids/42");
+                assert_eq!(kept, body);
+            }
+            other => panic!("expected an unexpected response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_cuts_long_bodies_short() {
+        let body = "é".repeat(MAX_ERROR_BODY_CHARS + 1);
+        match parse(502, &body) {
+            Err(Error::UnexpectedResponse { body: kept, .. }) => {
+                assert_eq!(kept, format!("{}...", "é".repeat(MAX_ERROR_BODY_CHARS)));
+            }
+            other => panic!("expected an unexpected response, got {:?}", other),
+        }
+        let body = "a".repeat(MAX_ERROR_BODY_CHARS);
+        assert_eq!(truncate_body(body.as_bytes()), body);
+    }
+}
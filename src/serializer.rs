@@ -1,25 +1,95 @@
+#[cfg(feature = "bytes")]
+use bytes::BytesMut;
+#[cfg(feature = "proto")]
+use prost_reflect::MessageDescriptor;
 use serde::Serialize;
 
+#[cfg(feature = "json")]
+use crate::json::{self, JsonSchema};
+#[cfg(feature = "proto")]
+use crate::proto;
 use crate::schema::Schema;
 use crate::schema_registry::SchemaRef;
 use crate::{Error, Result};
 
+/// How [`SchemaRegistry::get_serializer_for`](crate::SchemaRegistry::get_serializer_for) finds
+/// the registered schema matching a local one, the same as the `auto.register.schemas` and
+/// `use.latest.version` settings of Confluent's own serializers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SerializerMode {
+    /// Uses the local schema, registering it under the subject first if it isn't already
+    #[default]
+    AutoRegister,
+    /// Uses the version of the subject the schema details point at (normally the latest) rather
+    /// than the local schema, failing with [`Error::IncompatibleSchema`] unless the registry
+    /// considers the local schema compatible with it
+    UseLatest,
+    /// Uses the local schema, failing if it isn't already registered under the subject, so
+    /// nothing is ever written to the registry
+    LookupOnly,
+}
+
 #[derive(Clone)]
 pub enum Serializer {
+    #[cfg(feature = "avro")]
     Avro { schema: SchemaRef },
+    /// Serializes data as the `message`, which must be declared in the schema
+    #[cfg(feature = "proto")]
+    Protobuf {
+        schema: SchemaRef,
+        message: MessageDescriptor,
+    },
+    /// Serializes data as UTF-8 JSON, checking it against the schema first if `validate` is set
+    #[cfg(feature = "json")]
+    Json { schema: SchemaRef, validate: bool },
 }
 
 impl Serializer {
     pub fn serialize<S: Serialize>(&self, data: S) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.serialize_into(data, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Appends the serialized data to the end of `bytes`, so the same buffer can be reused for
+    /// every message
+    ///
+    /// The magic byte and schema id are written first, then Protobuf and JSON data is encoded
+    /// straight after them. Avro data is still encoded into a buffer of its own and copied in, as
+    /// avro-rs has no way to encode into an existing one. If serialization fails, `bytes` is left
+    /// as it was.
+    pub fn serialize_into<S: Serialize>(&self, data: S, bytes: &mut Vec<u8>) -> Result<()> {
+        self.write(data, bytes)
+    }
+
+    /// The same as [`serialize_into`](Self::serialize_into), appending to a `BytesMut` instead
+    #[cfg(feature = "bytes")]
+    pub fn serialize_into_bytes<S: Serialize>(&self, data: S, bytes: &mut BytesMut) -> Result<()> {
+        self.write(data, bytes)
+    }
+
+    fn write<S: Serialize, B: Buffer>(&self, data: S, bytes: &mut B) -> Result<()> {
+        let len = bytes.len();
+        let result = self.write_unchecked(data, bytes);
+        if result.is_err() {
+            bytes.truncate(len);
+        }
+        result
+    }
+
+    #[allow(irrefutable_let_patterns, unreachable_patterns)]
+    fn write_unchecked<S: Serialize, B: Buffer>(&self, data: S, bytes: &mut B) -> Result<()> {
         match *self {
+            #[cfg(feature = "avro")]
             Self::Avro { ref schema } => {
-                // Add magic bytes
-                let id = schema.id;
                 if let Schema::Avro(ref s) = &*schema.schema {
                     let value = avro_rs::to_value(data)?;
-                    let mut bytes = avro_rs::to_avro_datum(&s, value)?;
-                    let serialized_bytes = add_magic_byte_and_schema_id(&mut bytes, id);
-                    Ok(serialized_bytes)
+                    // avro-rs can only encode into a buffer of its own
+                    let datum = avro_rs::to_avro_datum(s, value)?;
+                    bytes.reserve(HEADER_LEN + datum.len());
+                    write_magic_byte_and_schema_id(bytes, schema.id);
+                    bytes.put_slice(&datum);
+                    Ok(())
                 } else {
                     Err(Error::IncorrectSchemaType(
                         "Avro".to_owned(),
@@ -27,14 +97,175 @@ impl Serializer {
                     ))
                 }
             }
+            #[cfg(feature = "proto")]
+            Self::Protobuf {
+                ref schema,
+                ref message,
+            } => {
+                write_magic_byte_and_schema_id(bytes, schema.id);
+                bytes.put_message(message, data)
+            }
+            #[cfg(feature = "json")]
+            Self::Json {
+                ref schema,
+                validate,
+            } => match &*schema.schema {
+                Schema::Json(s) => {
+                    write_magic_byte_and_schema_id(bytes, schema.id);
+                    bytes.put_json(s, data, validate)
+                }
+                s => Err(Error::IncorrectSchemaType(
+                    "Json".to_owned(),
+                    s.schema_type().to_string(),
+                )),
+            },
         }
     }
+
+    /// Checks data against the JSON schema before serializing it, failing with
+    /// [`Error::JsonSchemaViolation`] if it doesn't conform
+    ///
+    /// Validation is off by default; this has no effect on serializers for other formats
+    #[cfg(feature = "json")]
+    #[allow(unreachable_patterns)]
+    pub fn with_json_validation(self, validate: bool) -> Self {
+        match self {
+            Self::Json { schema, .. } => Self::Json { schema, validate },
+            other => other,
+        }
+    }
+
+    /// Switches the message that data will be serialized as, by its fully qualified name
+    ///
+    /// By default the message named by the subject naming strategy is used, or the first message
+    /// in the schema if the strategy doesn't name one
+    #[cfg(feature = "proto")]
+    #[allow(unreachable_patterns)]
+    pub fn with_message(self, name: &str) -> Result<Self> {
+        match self {
+            Self::Protobuf { schema, .. } => {
+                let message = match &*schema.schema {
+                    Schema::Protobuf(s) => s.message_by_name(name)?,
+                    s => {
+                        return Err(Error::IncorrectSchemaType(
+                            "Protobuf".to_owned(),
+                            s.schema_type().to_string(),
+                        ))
+                    }
+                };
+                Ok(Self::Protobuf { schema, message })
+            }
+            #[cfg(feature = "avro")]
+            Self::Avro { schema } => Err(Error::IncorrectSchemaType(
+                "Protobuf".to_owned(),
+                schema.schema.schema_type().to_string(),
+            )),
+            #[cfg(feature = "json")]
+            Self::Json { schema, .. } => Err(Error::IncorrectSchemaType(
+                "Protobuf".to_owned(),
+                schema.schema.schema_type().to_string(),
+            )),
+        }
+    }
+}
+
+/// The magic byte and the schema id
+#[cfg(feature = "avro")]
+const HEADER_LEN: usize = 5;
+
+fn write_magic_byte_and_schema_id<B: Buffer>(bytes: &mut B, id: u32) {
+    bytes.put_slice(&[0]);
+    bytes.put_slice(&id.to_be_bytes());
+}
+
+/// A buffer that serializers can encode data straight into
+trait Buffer {
+    fn len(&self) -> usize;
+
+    fn truncate(&mut self, len: usize);
+
+    #[cfg(feature = "avro")]
+    fn reserve(&mut self, additional: usize);
+
+    fn put_slice(&mut self, bytes: &[u8]);
+
+    #[cfg(feature = "proto")]
+    fn put_message<S: Serialize>(&mut self, message: &MessageDescriptor, data: S) -> Result<()>;
+
+    #[cfg(feature = "json")]
+    fn put_json<S: Serialize>(
+        &mut self,
+        schema: &JsonSchema,
+        data: S,
+        validate: bool,
+    ) -> Result<()>;
+}
+
+impl Buffer for Vec<u8> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len)
+    }
+
+    #[cfg(feature = "avro")]
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional)
+    }
+
+    fn put_slice(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes)
+    }
+
+    #[cfg(feature = "proto")]
+    fn put_message<S: Serialize>(&mut self, message: &MessageDescriptor, data: S) -> Result<()> {
+        proto::serialize(message, data, self)
+    }
+
+    #[cfg(feature = "json")]
+    fn put_json<S: Serialize>(
+        &mut self,
+        schema: &JsonSchema,
+        data: S,
+        validate: bool,
+    ) -> Result<()> {
+        json::serialize(schema, data, validate, self)
+    }
 }
 
-fn add_magic_byte_and_schema_id(payload: &mut Vec<u8>, id: u32) -> Vec<u8> {
-    let mut bytes = Vec::with_capacity(payload.len() + 5);
-    bytes.push(0);
-    bytes.append(&mut id.to_be_bytes().to_vec());
-    bytes.append(payload);
-    bytes
+#[cfg(feature = "bytes")]
+impl Buffer for BytesMut {
+    fn len(&self) -> usize {
+        BytesMut::len(self)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        BytesMut::truncate(self, len)
+    }
+
+    #[cfg(feature = "avro")]
+    fn reserve(&mut self, additional: usize) {
+        BytesMut::reserve(self, additional)
+    }
+
+    fn put_slice(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes)
+    }
+
+    #[cfg(feature = "proto")]
+    fn put_message<S: Serialize>(&mut self, message: &MessageDescriptor, data: S) -> Result<()> {
+        proto::serialize(message, data, self)
+    }
+
+    #[cfg(feature = "json")]
+    fn put_json<S: Serialize>(
+        &mut self,
+        schema: &JsonSchema,
+        data: S,
+        validate: bool,
+    ) -> Result<()> {
+        json::serialize(schema, data, validate, bytes::BufMut::writer(self))
+    }
 }
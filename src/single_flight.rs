@@ -0,0 +1,109 @@
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use futures_locks::Mutex;
+
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crate::{Error, Result};
+
+type Slot<T> = Arc<Mutex<Option<Outcome<T>>>>;
+
+/// How the work for a key ended, `None` in the slot means it was cancelled before it finished
+#[derive(Clone)]
+enum Outcome<T> {
+    Done(T),
+    Failed(Arc<Error>),
+}
+
+/// Coalesces concurrent requests for the same key, so that only the first caller does the work and
+/// everyone else waits for its result
+pub(crate) struct SingleFlight<K: Hash + Eq, T> {
+    in_flight: DashMap<K, Slot<T>>,
+}
+
+impl<K: Hash + Eq, T> Default for SingleFlight<K, T> {
+    fn default() -> Self {
+        Self {
+            in_flight: Default::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, T: Clone> SingleFlight<K, T> {
+    /// Runs `work` unless a call for `key` is already in flight, in which case its result is
+    /// awaited instead
+    ///
+    /// A failure is shared as an [`Error::Shared`] by everyone who waited on it, including the
+    /// caller that did the work, who only gets the error as it was when nobody else was waiting.
+    /// If the caller doing the work is cancelled, one of the waiters picks the work up instead.
+    pub(crate) async fn run<F, Fut>(&self, key: K, work: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        loop {
+            let (slot, guard) = match self.in_flight.entry(key.clone()) {
+                Entry::Occupied(e) => (Arc::clone(e.get()), None),
+                Entry::Vacant(e) => {
+                    let slot: Slot<T> = Arc::new(Mutex::new(None));
+                    let guard = slot
+                        .try_lock()
+                        .unwrap_or_else(|_| unreachable!("a new lock can't be held elsewhere"));
+                    e.insert(Arc::clone(&slot));
+                    (slot, Some(guard))
+                }
+            };
+
+            let mut guard = match guard {
+                Some(guard) => guard,
+                None => {
+                    let outcome = slot.lock().await.clone();
+                    match outcome {
+                        Some(Outcome::Done(value)) => return Ok(value),
+                        Some(Outcome::Failed(e)) => return Err(Error::Shared(e)),
+                        // Whoever was doing the work was cancelled before finishing
+                        None => continue,
+                    }
+                }
+            };
+
+            let leader = Leader {
+                in_flight: &self.in_flight,
+                key: &key,
+                slot: &slot,
+            };
+            let result = work().await;
+            drop(leader);
+            return match result {
+                Ok(value) => {
+                    *guard = Some(Outcome::Done(value.clone()));
+                    Ok(value)
+                }
+                // The entry is out of the map, so nobody else can start waiting on this slot
+                Err(e) if Arc::strong_count(&slot) == 1 => Err(e),
+                Err(e) => {
+                    let e = Arc::new(e);
+                    *guard = Some(Outcome::Failed(Arc::clone(&e)));
+                    Err(Error::Shared(e))
+                }
+            };
+        }
+    }
+}
+
+/// Takes the in flight entry out of the map once the work is done or abandoned, so later callers
+/// start a new request rather than joining a finished one
+struct Leader<'a, K: Hash + Eq, T> {
+    in_flight: &'a DashMap<K, Slot<T>>,
+    key: &'a K,
+    slot: &'a Slot<T>,
+}
+
+impl<'a, K: Hash + Eq, T> Drop for Leader<'a, K, T> {
+    fn drop(&mut self) {
+        self.in_flight
+            .remove_if(self.key, |_, slot| Arc::ptr_eq(slot, self.slot));
+    }
+}
@@ -1,10 +1,25 @@
+// Most of these tests register and serialize Avro schemas, so they need the `avro` feature
+#![cfg(feature = "avro")]
+
 use anyhow::Result;
 use schema_registry_converter::async_impl::avro::AvroEncoder;
 use schema_registry_converter::async_impl::schema_registry::SrSettings;
 use schema_registry_converter::schema_registry_common::SubjectNameStrategy as Strat;
 use serde::{Deserialize, Serialize};
 
-use confluent_schema_registry::{Format, SchemaDetails, SchemaRegistry, SubjectNamingStrategy};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use confluent_schema_registry::{
+    ArcDeserializer, CompatibilityLevel, Error, FailoverStrategy, Format, RegistryErrorCode,
+    RegistryMode, RetryPolicy, SchemaDetails, SchemaReference, SchemaRegistry, SerializerMode,
+    SubjectNamingStrategy, TokenError, TokenProvider,
+};
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 struct Test {
@@ -50,6 +65,7 @@ pub enum MetadataEventType {
     Message,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for MetadataEventType {
     fn default() -> Self {
         Self::Message
@@ -241,3 +257,1348 @@ async fn it_works_3() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn it_lists_subjects_and_versions() -> Result<()> {
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let raw_schema = r#"
+    {
+        "type": "record",
+        "name": "listed",
+        "fields": [
+            {"name": "a", "type": "long"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("listed-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    registry
+        .post_schemas_to_registry(&[(raw_schema, &details)])
+        .await?;
+
+    let subjects = registry.list_subjects(Some("listed-"), false).await?;
+    assert_eq!(subjects, vec!["listed-subject".to_owned()]);
+
+    let versions = registry.list_versions("listed-subject", false).await?;
+    assert!(!versions.is_empty());
+
+    let latest = registry
+        .get_subject_version_metadata("listed-subject", None)
+        .await?;
+    assert_eq!(latest.subject, "listed-subject");
+    assert_eq!(Some(&latest.version), versions.iter().max());
+    assert_eq!(latest.schema_type, Format::Avro);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_deletes_subjects_and_evicts_them() -> Result<()> {
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let raw_schema = r#"
+    {
+        "type": "record",
+        "name": "deleted",
+        "fields": [
+            {"name": "a", "type": "long"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("deleted-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    registry
+        .post_schemas_to_registry(&[(raw_schema, &details)])
+        .await?;
+    registry.get_serializer(&details).await?;
+
+    let deleted = registry.delete_subject("deleted-subject", false).await?;
+    assert!(!deleted.is_empty());
+    registry.delete_subject("deleted-subject", true).await?;
+
+    match registry.get_serializer(&details).await {
+        Err(Error::SchemaRegistryError {
+            status,
+            path,
+            error_code,
+            ..
+        }) => {
+            assert_eq!(status, 404);
+            assert!(path.contains("deleted-subject"));
+            assert_eq!(error_code, RegistryErrorCode::SubjectNotFound);
+            assert!(error_code.is_not_found());
+            assert!(!error_code.is_retryable());
+        }
+        other => panic!("expected the subject to be missing, got {:?}", other.err()),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_checks_compatibility() -> Result<()> {
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let raw_schema = r#"
+    {
+        "type": "record",
+        "name": "compatible",
+        "fields": [
+            {"name": "a", "type": "long"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("compatible-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    registry
+        .post_schemas_to_registry(&[(raw_schema, &details)])
+        .await?;
+
+    let evolved_schema = r#"
+    {
+        "type": "record",
+        "name": "compatible",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string", "default": ""}
+        ]
+    }
+    "#;
+    let check = registry
+        .check_compatibility(evolved_schema, &details)
+        .await?;
+    assert!(check.is_compatible);
+    assert!(check.messages.is_empty());
+
+    let incompatible_schema = r#"
+    {
+        "type": "record",
+        "name": "compatible",
+        "fields": [
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let check = registry
+        .check_compatibility(incompatible_schema, &details)
+        .await?;
+    assert!(!check.is_compatible);
+    assert!(!check.messages.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_manages_compatibility_config() -> Result<()> {
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let subject = "configured-subject";
+
+    let level = registry
+        .set_subject_compatibility(subject, CompatibilityLevel::FullTransitive)
+        .await?;
+    assert_eq!(level, CompatibilityLevel::FullTransitive);
+    let level = registry.get_subject_compatibility(subject, false).await?;
+    assert_eq!(level, CompatibilityLevel::FullTransitive);
+
+    let previous = registry.delete_subject_compatibility(subject).await?;
+    assert_eq!(previous, CompatibilityLevel::FullTransitive);
+    let global = registry.get_global_compatibility().await?;
+    let level = registry.get_subject_compatibility(subject, true).await?;
+    assert_eq!(level, global);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_imports_schemas_with_explicit_ids() -> Result<()> {
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let raw_schema = r#"
+    {
+        "type": "record",
+        "name": "imported",
+        "fields": [
+            {"name": "a", "type": "long"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: Some(7),
+        subject_naming_strategy: SubjectNamingStrategy::Custom("imported-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    let mode = registry
+        .set_subject_mode("imported-subject", RegistryMode::Import, true)
+        .await?;
+    assert_eq!(mode, RegistryMode::Import);
+
+    let id = registry
+        .import_schema(raw_schema, &details, 9001, 7)
+        .await?;
+    assert_eq!(id, 9001);
+
+    registry
+        .set_subject_mode("imported-subject", RegistryMode::ReadWrite, false)
+        .await?;
+    let metadata = registry
+        .get_subject_version_metadata("imported-subject", Some(7))
+        .await?;
+    assert_eq!(metadata.id, 9001);
+
+    Ok(())
+}
+
+/// Permanently deletes the subject so a test can start from scratch, whether or not an earlier
+/// run left it behind
+async fn delete_subject_if_exists(registry: &SchemaRegistry, subject: &str) -> Result<()> {
+    for permanent in [false, true] {
+        match registry.delete_subject(subject, permanent).await {
+            Err(e) if e.registry_error_code().is_some_and(|c| c.is_not_found()) => {}
+            result => {
+                result?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_looks_up_schemas_without_registering() -> Result<()> {
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    delete_subject_if_exists(&registry, "looked-up-subject").await?;
+    let raw_schema = r#"
+    {
+        "type": "record",
+        "name": "looked_up",
+        "fields": [
+            {"name": "a", "type": "long"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("looked-up-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    assert!(registry
+        .lookup_schema(raw_schema, &details)
+        .await?
+        .is_none());
+    assert!(registry
+        .list_subjects(Some("looked-up-"), false)
+        .await?
+        .is_empty());
+
+    registry
+        .post_schemas_to_registry(&[(raw_schema, &details)])
+        .await?;
+    let schema = registry.lookup_schema(raw_schema, &details).await?;
+    let metadata = registry
+        .get_subject_version_metadata("looked-up-subject", None)
+        .await?;
+    assert_eq!(schema.map(|s| s.id()), Some(metadata.id));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_gets_serializers_for_local_schemas() -> Result<()> {
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    delete_subject_if_exists(&registry, "local-subject").await?;
+    let raw_schema = r#"
+    {
+        "type": "record",
+        "name": "local",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("local-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+
+    let error = registry
+        .get_serializer_for(raw_schema, &details, SerializerMode::LookupOnly)
+        .await
+        .err()
+        .and_then(|e| e.registry_error_code());
+    assert_eq!(error, Some(RegistryErrorCode::SubjectNotFound));
+
+    let serializer = registry
+        .get_serializer_for(raw_schema, &details, SerializerMode::AutoRegister)
+        .await?;
+    let data = Test {
+        a: 1,
+        b: "local".to_owned(),
+    };
+    let bytes = serializer.serialize(&data)?;
+    let result: Test = registry
+        .get_deserializer()
+        .deserialize(&bytes, Format::Avro)
+        .await?;
+    assert_eq!(data, result);
+
+    let looked_up = registry
+        .get_serializer_for(raw_schema, &details, SerializerMode::LookupOnly)
+        .await?;
+    assert_eq!(looked_up.serialize(&data)?, bytes);
+
+    let evolved_schema = r#"
+    {
+        "type": "record",
+        "name": "local",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"},
+            {"name": "c", "type": "string", "default": ""}
+        ]
+    }
+    "#;
+    let latest = registry
+        .get_serializer_for(evolved_schema, &details, SerializerMode::UseLatest)
+        .await?;
+    assert_eq!(latest.serialize(&data)?, bytes);
+
+    let incompatible_schema = r#"
+    {
+        "type": "record",
+        "name": "local",
+        "fields": [
+            {"name": "c", "type": "string"}
+        ]
+    }
+    "#;
+    let result = registry
+        .get_serializer_for(incompatible_schema, &details, SerializerMode::UseLatest)
+        .await;
+    assert!(matches!(result, Err(Error::IncompatibleSchema(_))));
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct Envelope {
+    id: String,
+    common: Common,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct Common {
+    source: String,
+}
+
+#[tokio::test]
+async fn it_resolves_schema_references() -> Result<()> {
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let common_schema = r#"
+    {
+        "type": "record",
+        "name": "Common",
+        "namespace": "awesome.shared",
+        "fields": [
+            {"name": "source", "type": "string"}
+        ]
+    }
+    "#;
+    let envelope_schema = r#"
+    {
+        "type": "record",
+        "name": "Envelope",
+        "namespace": "awesome.events",
+        "fields": [
+            {"name": "id", "type": "string"},
+            {"name": "common", "type": "awesome.shared.Common"}
+        ]
+    }
+    "#;
+    let common_details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("common".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    let envelope_details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::TopicNameStrategy {
+            topic_name: "enveloped".into(),
+            is_key: false,
+        },
+        schema_references: vec![SchemaReference {
+            name: "awesome.shared.Common".into(),
+            details: common_details.clone(),
+        }],
+        format: Default::default(),
+    };
+    registry
+        .post_schemas_to_registry(&[
+            (common_schema, &common_details),
+            (envelope_schema, &envelope_details),
+        ])
+        .await?;
+
+    let metadata = registry
+        .get_subject_version_metadata("enveloped-value", None)
+        .await?;
+    assert_eq!(metadata.references.len(), 1);
+    assert_eq!(metadata.references[0].subject, "common");
+
+    let test = Envelope {
+        id: "abc".into(),
+        common: Common {
+            source: "tests".into(),
+        },
+    };
+    let serializer = registry.get_serializer(&envelope_details).await?;
+    let bytes = serializer.serialize(&test)?;
+
+    // Use a fresh registry so the schema has to be fetched by ID and resolved again
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let deserializer = registry.get_deserializer();
+    let result = deserializer.deserialize(&bytes, Format::Avro).await?;
+    assert_eq!(test, result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_caches_deserializer_schemas_per_id() -> Result<()> {
+    #[derive(Serialize)]
+    struct Flipped {
+        b: String,
+        a: i64,
+    }
+
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let first_schema = r#"
+    {
+        "type": "record",
+        "name": "mixed",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let second_schema = r#"
+    {
+        "type": "record",
+        "name": "mixed",
+        "fields": [
+            {"name": "b", "type": "string"},
+            {"name": "a", "type": "long"}
+        ]
+    }
+    "#;
+    let mut details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("mixed-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    registry
+        .post_schemas_to_registry(&[(first_schema, &details), (second_schema, &details)])
+        .await?;
+    let data = Test {
+        a: 1,
+        b: "mixed".into(),
+    };
+    details.version = Some(1);
+    let first_bytes = registry.get_serializer(&details).await?.serialize(&data)?;
+    details.version = Some(2);
+    let second_bytes = registry
+        .get_serializer(&details)
+        .await?
+        .serialize(&Flipped {
+            b: data.b.clone(),
+            a: data.a,
+        })?;
+    assert_ne!(first_bytes[1..5], second_bytes[1..5]);
+
+    let deserializer = registry.get_cached_deserializer();
+    for bytes in [&first_bytes, &second_bytes, &first_bytes] {
+        let result: Test = deserializer.deserialize(bytes, Format::Avro).await?;
+        assert_eq!(data, result);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_refreshes_the_latest_version() -> Result<()> {
+    let first_schema = r#"
+    {
+        "type": "record",
+        "name": "refreshed",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let second_schema = r#"
+    {
+        "type": "record",
+        "name": "refreshed",
+        "doc": "The second version",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("refreshed-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    let data = Test {
+        a: 1,
+        b: "refreshed".into(),
+    };
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    delete_subject_if_exists(&registry, "refreshed-subject").await?;
+    let expiring_registry = SchemaRegistry::builder("http://localhost:8081")
+        .latest_ttl(Duration::from_millis(100))
+        .build()?;
+    registry
+        .post_schemas_to_registry(&[(first_schema, &details)])
+        .await?;
+    let first_id = registry.get_serializer(&details).await?.serialize(&data)?[1..5].to_vec();
+    let expiring_id = expiring_registry
+        .get_serializer(&details)
+        .await?
+        .serialize(&data)?[1..5]
+        .to_vec();
+    assert_eq!(first_id, expiring_id);
+
+    SchemaRegistry::new("http://localhost:8081".to_owned())
+        .post_schemas_to_registry(&[(second_schema, &details)])
+        .await?;
+    let cached_id = registry.get_serializer(&details).await?.serialize(&data)?[1..5].to_vec();
+    assert_eq!(first_id, cached_id);
+
+    registry.invalidate_subject("refreshed-subject");
+    let second_id = registry.get_serializer(&details).await?.serialize(&data)?[1..5].to_vec();
+    assert_ne!(first_id, second_id);
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let expiring_id = expiring_registry
+        .get_serializer(&details)
+        .await?
+        .serialize(&data)?[1..5]
+        .to_vec();
+    assert_eq!(second_id, expiring_id);
+
+    // Posting an older version again doesn't make it the latest
+    let reposting_registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    reposting_registry
+        .post_schemas_to_registry(&[(first_schema, &details)])
+        .await?;
+    let latest_id = reposting_registry
+        .get_serializer(&details)
+        .await?
+        .serialize(&data)?[1..5]
+        .to_vec();
+    assert_eq!(second_id, latest_id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_evicts_least_recently_used_schemas() -> Result<()> {
+    let first_schema = r#"
+    {
+        "type": "record",
+        "name": "bounded",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let second_schema = r#"
+    {
+        "type": "record",
+        "name": "bounded",
+        "doc": "The second version",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let mut details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("bounded-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    registry
+        .post_schemas_to_registry(&[(first_schema, &details), (second_schema, &details)])
+        .await?;
+    let data = Test {
+        a: 1,
+        b: "bounded".into(),
+    };
+    details.version = Some(1);
+    let first_bytes = registry.get_serializer(&details).await?.serialize(&data)?;
+    details.version = Some(2);
+    let second_bytes = registry.get_serializer(&details).await?.serialize(&data)?;
+
+    let registry = SchemaRegistry::builder("http://localhost:8081")
+        .max_cached_schemas(1)
+        .build()?;
+    let deserializer = registry.get_deserializer();
+    for bytes in [&first_bytes, &first_bytes, &second_bytes, &first_bytes] {
+        let result: Test = deserializer.deserialize(bytes, Format::Avro).await?;
+        assert_eq!(data, result);
+    }
+    let stats = registry.cache_stats();
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 3);
+    assert_eq!(stats.evictions, 2);
+    assert_eq!(stats.entries, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_loads_schemas_from_the_disk_cache() -> Result<()> {
+    let raw_schema = r#"
+    {
+        "type": "record",
+        "name": "offline",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("offline-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    let dir = std::env::temp_dir().join(format!("schema-cache-{}", std::process::id()));
+    let registry = SchemaRegistry::builder("http://localhost:8081")
+        .disk_cache(&dir)
+        .build()?;
+    registry
+        .post_schemas_to_registry(&[(raw_schema, &details)])
+        .await?;
+    let data = Test {
+        a: 1,
+        b: "offline".into(),
+    };
+    let bytes = registry.get_serializer(&details).await?.serialize(&data)?;
+
+    // Nothing is listening here, so the schema can only come from the disk cache
+    let offline_registry = SchemaRegistry::builder("http://localhost:1")
+        .disk_cache(&dir)
+        .build()?;
+    let result: Test = offline_registry
+        .get_deserializer()
+        .deserialize(&bytes, Format::Avro)
+        .await?;
+    assert_eq!(data, result);
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_exports_and_imports_cache_snapshots() -> Result<()> {
+    let raw_schema = r#"
+    {
+        "type": "record",
+        "name": "snapshot",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("snapshot-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    registry
+        .post_schemas_to_registry(&[(raw_schema, &details)])
+        .await?;
+    let data = Test {
+        a: 1,
+        b: "snapshot".into(),
+    };
+    let bytes = registry.get_serializer(&details).await?.serialize(&data)?;
+    let snapshot = serde_json::to_string(&registry.export_cache())?;
+
+    // Nothing is listening here, so everything has to come from the snapshot
+    let offline_registry = SchemaRegistry::new("http://localhost:1".to_owned());
+    offline_registry.import_cache(serde_json::from_str(&snapshot)?)?;
+    let offline_bytes = offline_registry
+        .get_serializer(&details)
+        .await?
+        .serialize(&data)?;
+    assert_eq!(bytes, offline_bytes);
+    let result: Test = offline_registry
+        .get_deserializer()
+        .deserialize(&bytes, Format::Avro)
+        .await?;
+    assert_eq!(data, result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_prefetches_schemas() -> Result<()> {
+    let first_schema = r#"
+    {
+        "type": "record",
+        "name": "prefetched",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let second_schema = r#"
+    {
+        "type": "record",
+        "name": "prefetched",
+        "doc": "The second version",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("prefetched-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    registry
+        .post_schemas_to_registry(&[(first_schema, &details), (second_schema, &details)])
+        .await?;
+    let versions: Vec<_> = [1, 2]
+        .iter()
+        .map(|&version| SchemaDetails {
+            version: Some(version),
+            ..details.clone()
+        })
+        .collect();
+
+    let registry = SchemaRegistry::builder("http://localhost:8081")
+        .prefetch_concurrency(2)
+        .build()?;
+    registry.prefetch(&versions).await?;
+    assert_eq!(registry.cache_stats().entries, 2);
+
+    let data = Test {
+        a: 1,
+        b: "prefetched".into(),
+    };
+    let bytes = registry
+        .get_serializer(&versions[0])
+        .await?
+        .serialize(&data)?;
+    let id = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    registry.prefetch_ids(&[id]).await?;
+    let misses = registry.cache_stats().misses;
+    let result: Test = registry
+        .get_deserializer()
+        .deserialize(&bytes, Format::Avro)
+        .await?;
+    assert_eq!(data, result);
+    assert_eq!(registry.cache_stats().misses, misses);
+
+    Ok(())
+}
+
+/// Hands out the same token every time, counting how many requests have asked for one
+struct CountingProvider(Arc<AtomicUsize>);
+
+impl TokenProvider for CountingProvider {
+    fn token(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<String, TokenError>> + Send + '_>> {
+        Box::pin(async move {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok("token".to_owned())
+        })
+    }
+}
+
+#[tokio::test]
+async fn it_authenticates_with_a_token_provider() -> Result<()> {
+    let (url, requests) = stub_registry(&[NO_SUBJECTS]).await?;
+    let calls = Arc::new(AtomicUsize::new(0));
+    let registry = SchemaRegistry::builder(url.as_str())
+        .token_provider(CountingProvider(Arc::clone(&calls)))
+        .build()?;
+    registry.list_subjects(None, false).await?;
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    let registry = SchemaRegistry::builder(url.as_str())
+        .basic_auth("key", Some("secret".to_owned()))
+        .build()?;
+    registry.list_subjects(None, false).await?;
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert!(has_header(&requests[0], "authorization", "Bearer token"));
+    // "key:secret" in base64
+    assert!(has_header(
+        &requests[1],
+        "authorization",
+        "Basic a2V5OnNlY3JldA=="
+    ));
+
+    Ok(())
+}
+
+fn has_header(request: &str, name: &str, value: &str) -> bool {
+    request.lines().any(|line| match line.split_once(':') {
+        Some((n, v)) => n.eq_ignore_ascii_case(name) && v.trim() == value,
+        None => false,
+    })
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+#[test]
+fn it_rejects_invalid_tls_options() {
+    let result = SchemaRegistry::builder("https://localhost:8081")
+        .root_certificate_file("/does/not/exist.pem")
+        .build();
+    assert!(matches!(result, Err(Error::Io(_))));
+
+    let result = SchemaRegistry::builder("https://localhost:8081")
+        .client_identity("not a certificate", "not a key")
+        .build();
+    assert!(matches!(result, Err(Error::Http(_))));
+
+    let result = SchemaRegistry::builder("https://localhost:8081")
+        .client(Default::default())
+        .root_certificate("not a certificate")
+        .build();
+    assert!(matches!(result, Err(Error::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn it_fails_over_to_healthy_registries() -> Result<()> {
+    // Nothing is listening on the first URL
+    let registry = SchemaRegistry::new("http://localhost:1,http://localhost:8081".to_owned());
+    registry.list_subjects(None, false).await?;
+
+    let registry = SchemaRegistry::builder("http://localhost:1")
+        .add_url("http://localhost:8081")
+        .failover(FailoverStrategy::RoundRobin)
+        .build()?;
+    for _ in 0..4 {
+        registry.list_subjects(None, false).await?;
+    }
+
+    Ok(())
+}
+
+/// The raw requests a stub registry has been sent, in the order they arrived
+type StubRequests = Arc<Mutex<Vec<String>>>;
+
+/// Serves the raw HTTP `responses` in turn on a local port, repeating the last one once they run
+/// out, returning its URL along with the requests it has answered
+async fn stub_registry(responses: &'static [&'static str]) -> Result<(String, StubRequests)> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let requests = StubRequests::default();
+    let received = Arc::clone(&requests);
+    tokio::spawn(async move {
+        let mut n = 0;
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let response = responses[n.min(responses.len() - 1)];
+            n += 1;
+            let received = Arc::clone(&received);
+            tokio::spawn(async move {
+                let mut request = [0; 4096];
+                let len = stream.read(&mut request).await.unwrap_or(0);
+                received
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&request[..len]).into_owned());
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    Ok((url, requests))
+}
+
+const NO_SUBJECTS: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]";
+
+const SERVICE_UNAVAILABLE: &str =
+    "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+#[tokio::test]
+async fn it_fails_over_on_server_errors() -> Result<()> {
+    let (unavailable, requests) = stub_registry(&[SERVICE_UNAVAILABLE]).await?;
+
+    let registry = SchemaRegistry::builder(unavailable.as_str())
+        .add_url("http://localhost:8081")
+        .build()?;
+    registry.list_subjects(None, false).await?;
+    assert_eq!(requests.lock().unwrap().len(), 1);
+    // The failed URL is tried last until its cooldown is over
+    registry.list_subjects(None, false).await?;
+    assert_eq!(requests.lock().unwrap().len(), 1);
+
+    let registry = SchemaRegistry::builder(unavailable.as_str())
+        .add_url("http://localhost:8081")
+        .unhealthy_cooldown(Duration::ZERO)
+        .build()?;
+    registry.list_subjects(None, false).await?;
+    registry.list_subjects(None, false).await?;
+    assert_eq!(requests.lock().unwrap().len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn it_needs_at_least_one_url() {
+    for url in ["", " , "] {
+        let result = SchemaRegistry::builder(url).build();
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+}
+
+#[tokio::test]
+async fn it_retries_with_backoff() -> Result<()> {
+    // Nothing is listening here, so every attempt fails to connect
+    let registry = SchemaRegistry::builder("http://localhost:1")
+        .retry_policy(RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(50),
+            jitter: false,
+            ..Default::default()
+        })
+        .build()?;
+    let started = std::time::Instant::now();
+    let result = registry.list_subjects(None, false).await;
+    assert!(matches!(result, Err(Error::RegistryUnreachable { .. })));
+    assert!(started.elapsed() >= Duration::from_millis(150));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_retries_on_retryable_statuses() -> Result<()> {
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(10),
+        jitter: false,
+        ..Default::default()
+    };
+
+    let (url, requests) = stub_registry(&[
+        "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        SERVICE_UNAVAILABLE,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 9\r\nConnection: close\r\n\r\n[\"retry\"]",
+    ])
+    .await?;
+    let registry = SchemaRegistry::builder(url)
+        .retry_policy(policy.clone())
+        .build()?;
+    assert_eq!(registry.list_subjects(None, false).await?, vec!["retry"]);
+    assert_eq!(requests.lock().unwrap().len(), 3);
+
+    let (url, requests) = stub_registry(&[SERVICE_UNAVAILABLE]).await?;
+    let registry = SchemaRegistry::builder(url).retry_policy(policy).build()?;
+    let result = registry.list_subjects(None, false).await;
+    assert!(matches!(
+        result,
+        Err(Error::UnexpectedResponse { status, .. }) if status == 503
+    ));
+    assert_eq!(requests.lock().unwrap().len(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_shares_concurrent_fetches() -> Result<()> {
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let raw_schema = r#"
+    {
+        "type": "record",
+        "name": "shared",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("shared-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    registry
+        .post_schemas_to_registry(&[(raw_schema, &details)])
+        .await?;
+    let data = Test {
+        a: 1,
+        b: "shared".into(),
+    };
+    let bytes = registry.get_serializer(&details).await?.serialize(&data)?;
+    let mut missing = bytes.clone();
+    missing[1..5].copy_from_slice(&u32::MAX.to_be_bytes());
+
+    // Every request asks the provider for a token, so it counts the requests made
+    let requests = Arc::new(AtomicUsize::new(0));
+    let registry = Arc::new(
+        SchemaRegistry::builder("http://localhost:8081")
+            .token_provider(CountingProvider(Arc::clone(&requests)))
+            .build()?,
+    );
+    let mut tasks = tokio::task::JoinSet::new();
+    for _ in 0..50 {
+        let deserializer = ArcDeserializer::new(Arc::clone(&registry));
+        let bytes = bytes.clone();
+        tasks.spawn(async move { deserializer.deserialize::<Test>(&bytes, Format::Avro).await });
+    }
+    while let Some(result) = tasks.join_next().await {
+        assert_eq!(data, result??);
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    // Failures are shared too, and still carry the registry's error code
+    let mut tasks = tokio::task::JoinSet::new();
+    for _ in 0..50 {
+        let deserializer = ArcDeserializer::new(Arc::clone(&registry));
+        let missing = missing.clone();
+        tasks.spawn(async move {
+            deserializer
+                .deserialize::<Test>(&missing, Format::Avro)
+                .await
+        });
+    }
+    while let Some(result) = tasks.join_next().await {
+        let error = result?.err().and_then(|e| e.registry_error_code());
+        assert_eq!(error, Some(RegistryErrorCode::SchemaNotFound));
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_resolves_payloads_against_a_reader_schema() -> Result<()> {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Evolved {
+        a: i64,
+        c: String,
+    }
+
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    delete_subject_if_exists(&registry, "evolving-subject").await?;
+    let writer_schema = r#"
+    {
+        "type": "record",
+        "name": "evolving",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let reader_schema = r#"
+    {
+        "type": "record",
+        "name": "evolving",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "c", "type": "string", "default": "fallback"}
+        ]
+    }
+    "#;
+    let mut details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("evolving-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    registry
+        .post_schemas_to_registry(&[(writer_schema, &details)])
+        .await?;
+    let bytes = registry.get_serializer(&details).await?.serialize(&Test {
+        a: 1,
+        b: "removed".into(),
+    })?;
+    let result: Evolved = registry
+        .get_deserializer()
+        .with_reader_schema(confluent_schema_registry::avro::Schema::parse_str(
+            reader_schema,
+        )?)
+        .deserialize(&bytes, Format::Avro)
+        .await?;
+    let expected = Evolved {
+        a: 1,
+        c: "fallback".into(),
+    };
+    assert_eq!(expected, result);
+
+    registry
+        .post_schemas_to_registry(&[(reader_schema, &details)])
+        .await?;
+    details.version = Some(2);
+    let result: Evolved = registry
+        .get_deserializer()
+        .with_reader_schema_details(&details)
+        .await?
+        .deserialize(&bytes, Format::Avro)
+        .await?;
+    assert_eq!(expected, result);
+
+    Ok(())
+}
+
+#[cfg(feature = "proto")]
+#[tokio::test]
+async fn it_works_with_protobuf() -> Result<()> {
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Room {
+        name: String,
+        owner: User,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct User {
+        user_id: String,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Member {
+        user_id: String,
+        joined_at: i64,
+    }
+
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let user_schema = r#"
+    syntax = "proto3";
+    package awesome.shared;
+
+    message User {
+      string user_id = 1;
+    }
+    "#;
+    let room_schema = r#"
+    syntax = "proto3";
+    package awesome.chatroom;
+
+    import "awesome/shared/user.proto";
+
+    message Room {
+      string name = 1;
+      awesome.shared.User owner = 2;
+
+      message Settings {
+        bool muted = 1;
+      }
+
+      message Member {
+        string user_id = 1;
+        int64 joined_at = 2;
+      }
+    }
+    "#;
+    let user_details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("awesome-shared-user".into()),
+        schema_references: Default::default(),
+        format: Format::Protobuf,
+    };
+    let room_details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::TopicNameStrategy {
+            topic_name: "rooms".into(),
+            is_key: false,
+        },
+        schema_references: vec![SchemaReference {
+            name: "awesome/shared/user.proto".into(),
+            details: user_details.clone(),
+        }],
+        format: Format::Protobuf,
+    };
+    registry
+        .post_schemas_to_registry(&[(user_schema, &user_details), (room_schema, &room_details)])
+        .await?;
+
+    let room = Room {
+        name: "general".into(),
+        owner: User {
+            user_id: "abc".into(),
+        },
+    };
+    let serializer = registry.get_serializer(&room_details).await?;
+    let bytes = serializer.serialize(&room)?;
+    // The first message in the file is encoded as a single 0
+    assert_eq!(bytes[5], 0);
+
+    let member = Member {
+        user_id: "abc".into(),
+        joined_at: 1_614_000_000_000,
+    };
+    let member_bytes = serializer
+        .with_message("awesome.chatroom.Room.Member")?
+        .serialize(&member)?;
+    // [0, 1] is encoded as a zig-zag length of 2, followed by each zig-zag index
+    assert_eq!(member_bytes[5..8], [4, 0, 2]);
+
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let deserializer = registry.get_deserializer();
+    let result: Room = deserializer.deserialize(&bytes, Format::Protobuf).await?;
+    assert_eq!(room, result);
+    let result: Member = deserializer
+        .deserialize(&member_bytes, Format::Protobuf)
+        .await?;
+    assert_eq!(member, result);
+
+    Ok(())
+}
+
+#[cfg(feature = "json")]
+#[tokio::test]
+async fn it_works_with_json_schema() -> Result<()> {
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let schema = r#"{
+        "type": "object",
+        "properties": {
+            "a": { "type": "integer" },
+            "b": { "type": "string", "minLength": 1 }
+        },
+        "required": ["a", "b"]
+    }"#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::TopicNameStrategy {
+            topic_name: "json-ingest".into(),
+            is_key: false,
+        },
+        schema_references: Default::default(),
+        format: Format::Json,
+    };
+    registry
+        .post_schemas_to_registry(&[(schema, &details)])
+        .await?;
+
+    let serializer = registry.get_serializer(&details).await?;
+    let valid = Test {
+        a: 1,
+        b: "one".into(),
+    };
+    let bytes = serializer.serialize(&valid)?;
+    assert_eq!(&bytes[5..], br#"{"a":1,"b":"one"}"#);
+
+    let invalid = Test {
+        a: 2,
+        b: String::new(),
+    };
+    let invalid_bytes = serializer.serialize(&invalid)?;
+    let validating_serializer = serializer.with_json_validation(true);
+    assert!(matches!(
+        validating_serializer.serialize(&invalid),
+        Err(Error::JsonSchemaViolation(_))
+    ));
+
+    let deserializer = registry.get_deserializer();
+    let result: Test = deserializer.deserialize(&bytes, Format::Json).await?;
+    assert_eq!(valid, result);
+    let result: Test = deserializer
+        .deserialize(&invalid_bytes, Format::Json)
+        .await?;
+    assert_eq!(invalid, result);
+    let result: std::result::Result<Test, _> = deserializer
+        .with_json_validation(true)
+        .deserialize(&invalid_bytes, Format::Json)
+        .await;
+    assert!(matches!(result, Err(Error::JsonSchemaViolation(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_serializes_into_a_reused_buffer() -> Result<()> {
+    let registry = SchemaRegistry::new("http://localhost:8081".to_owned());
+    let raw_schema = r#"
+    {
+        "type": "record",
+        "name": "buffered",
+        "fields": [
+            {"name": "a", "type": "long"},
+            {"name": "b", "type": "string"}
+        ]
+    }
+    "#;
+    let details = SchemaDetails {
+        version: None,
+        subject_naming_strategy: SubjectNamingStrategy::Custom("buffered-subject".into()),
+        schema_references: Default::default(),
+        format: Default::default(),
+    };
+    let serializer = registry
+        .get_serializer_for(raw_schema, &details, SerializerMode::AutoRegister)
+        .await?;
+    let test = Test {
+        a: 100,
+        b: String::from("My Test"),
+    };
+    let expected = serializer.serialize(&test)?;
+
+    let mut bytes = Vec::new();
+    for _ in 0..2 {
+        bytes.clear();
+        serializer.serialize_into(&test, &mut bytes)?;
+        assert_eq!(bytes, expected);
+    }
+
+    // Data that doesn't fit the schema leaves the buffer untouched
+    #[derive(Serialize)]
+    struct Mismatched {
+        c: bool,
+    }
+    let result = serializer.serialize_into(Mismatched { c: true }, &mut bytes);
+    assert!(result.is_err());
+    assert_eq!(bytes, expected);
+
+    #[cfg(feature = "bytes")]
+    {
+        let mut bytes = bytes::BytesMut::new();
+        serializer.serialize_into_bytes(&test, &mut bytes)?;
+        assert_eq!(&bytes[..], &expected[..]);
+    }
+
+    Ok(())
+}